@@ -0,0 +1,18 @@
+; Reads the radius of a circle and prints its area, its circumference and the area rounded
+; toward zero. Negative radii are made positive first.
+
+.data
+:pi 3.1415927
+
+.code
+	fin
+	dup 0.0 flt ; is the radius below 0.0?
+	0 @positive je
+	fneg
+:positive
+	dup dup fmul @pi load fmul ; area = pi * r * r
+	dup fout
+	swp
+	2 itof fmul @pi load fmul ; circumference = 2 * pi * r
+	fout
+	ftoi out
@@ -0,0 +1,16 @@
+; Roll five dice using memory-mapped devices. Run with
+;   svm --device random@1024 --device console@1025 examples/dice/dice
+; 1024 - random number generator
+; 1025 - console character output, 1026 - console number output
+
+5 0 stor ; dice left to roll
+:roll
+	1024 load 6 mod inc 1026 stor
+	0 load dec dup 0 stor
+	0 @roll jl
+"done\n"
+:print
+	dup 0 @end je
+	1025 stor
+	@print jmp
+:end
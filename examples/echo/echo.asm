@@ -0,0 +1,9 @@
+; Copy standard input to standard output one character at a time
+rf
+:loop
+	in
+	dup 1 neg @end je ; IN pushes -1 once the input is exhausted
+	out
+	@loop jmp
+:end
+pop
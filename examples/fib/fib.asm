@@ -0,0 +1,15 @@
+; Recursive Fibonacci using CALL and RET
+in
+@fib call
+out
+halt
+
+; Replaces N on top of the stack with the N-th Fibonacci number
+:fib
+	dup 1 @fib_rec jl ; Recurse if N > 1
+	ret
+:fib_rec
+	dup dec @fib call   ; The stack will be <N fib(N-1)>
+	swp 2 sub @fib call ; The stack will be <fib(N-1) fib(N-2)>
+	add
+	ret
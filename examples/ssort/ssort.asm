@@ -1,5 +1,7 @@
 ; Selection sort for SVM
 
+.memory 65536 ; room for large sequences
+
 ; Read N
 "Please enter the number of elements in the sequence\n"
 print
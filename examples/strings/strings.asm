@@ -0,0 +1,23 @@
+; Print strings stored in the data segment instead of pushing them onto the stack.
+
+.data
+:greeting "Hello from the data segment!\n"
+:farewell "Goodbye.\n"
+
+.code
+	@greeting @puts call
+	@farewell @puts call
+	halt
+
+; Prints the string starting at the address on top of the stack.
+:puts
+	rf
+:puts_loop
+	dup load
+	dup 0 @puts_end je
+	out
+	inc @puts_loop jmp
+:puts_end
+	pop pop
+	crf
+	ret
@@ -0,0 +1,44 @@
+; Count the lines, words and characters of standard input
+
+; Memory layout:
+; 0 - lines
+; 1 - words
+; 2 - characters
+; 3 - 1 if the previous character was part of a word
+
+rf
+:loop
+	in
+	dup 1 neg @end je
+
+	; Count the character
+	2 load inc 2 stor
+
+	; Count the line
+	dup 10 @no_newline jne
+		0 load inc 0 stor
+	:no_newline
+
+	; Spaces, tabs and newlines separate words
+	dup 32 @space je
+	dup 9 @space je
+	dup 10 @space je
+
+	; Count the word when its first character is read
+	pop
+	3 load 1 @loop je
+	1 load inc 1 stor
+	1 3 stor
+	@loop jmp
+
+	:space
+	pop
+	0 3 stor
+	@loop jmp
+:end
+pop
+crf
+
+0 load out
+1 load out
+2 load out
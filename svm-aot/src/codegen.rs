@@ -0,0 +1,202 @@
+use std::fmt::Write;
+
+use svm::instructions::*;
+use svm::program::Program;
+use svm::{ErrorKind, VMConfig, MAX_EXIT_STATUS};
+
+/// Runtime support prepended to every translated program.
+const RUNTIME: &str = include_str!("runtime.c");
+
+/// Translates `program` into a standalone C program that behaves like running it with `svm`.
+///
+/// Every address gets a label, so jumps to a literal address become a `goto` and the others go
+/// through a `switch` over the whole program. The stack and memory are sized like a VM created
+/// with `config` would size them after loading `program`, and memory starts out with its data.
+pub fn translate(program: &Program, config: &VMConfig) -> String {
+    let code = &program.code;
+    let stack_size = config.stack_size.max(program.stack_size.unwrap_or(0));
+    let memory_size = (config.memory_size)
+        .max(program.memory_size.unwrap_or(0))
+        .max(program.data.len());
+
+    let mut out = String::new();
+    writeln!(out, "/* Generated by svm-aot. */").unwrap();
+    writeln!(out, "#define PROGRAM_LEN {}", code.len()).unwrap();
+    writeln!(out, "#define STACK_SIZE {stack_size}").unwrap();
+    writeln!(out, "#define MEMORY_SIZE {memory_size}").unwrap();
+    writeln!(
+        out,
+        "#define GROWABLE_STACK {}",
+        config.growable_stack as u8
+    )
+    .unwrap();
+    writeln!(out, "#define MAX_STACK_SIZE {}", config.max_stack_size).unwrap();
+    writeln!(out, "#define CALL_STACK_SIZE {}", config.call_stack_size).unwrap();
+    writeln!(out, "#define DATA_LEN {}", program.data.len()).unwrap();
+    write!(out, "#define DATA").unwrap();
+    for v in &program.data {
+        write!(out, " {},", c_int(*v)).unwrap();
+    }
+    writeln!(out).unwrap();
+    for (name, kind) in [
+        ("STACK", ErrorKind::StackOverflow),
+        ("CALL_STACK", ErrorKind::CallStackOverflow),
+        ("MEMORY", ErrorKind::InvalidMemoryAddress),
+        ("JUMP", ErrorKind::InvalidJumpTarget(0)),
+        ("INSTRUCTION", ErrorKind::UnknownInstruction(0)),
+        ("ARITHMETIC", ErrorKind::DivisionByZero),
+        ("IO", ErrorKind::IOError),
+        ("HOST", ErrorKind::UnknownHostFunction(0)),
+    ] {
+        writeln!(out, "#define EXIT_{name} {}", kind.exit_code()).unwrap();
+    }
+    writeln!(out, "#define MAX_EXIT_STATUS {MAX_EXIT_STATUS}").unwrap();
+    writeln!(out).unwrap();
+    out.push_str(RUNTIME);
+
+    writeln!(out).unwrap();
+    writeln!(out, "static void run(void)").unwrap();
+    writeln!(out, "{{").unwrap();
+    writeln!(out, "    size_t ip;").unwrap();
+    for addr in 0..code.len() {
+        writeln!(out, "L{addr}:").unwrap();
+        writeln!(out, "    {}", translate_instruction(code, addr)).unwrap();
+    }
+    writeln!(out, "    goto end;").unwrap();
+    writeln!(out, "dispatch:").unwrap();
+    writeln!(out, "    switch (ip) {{").unwrap();
+    for addr in 0..code.len() {
+        writeln!(out, "    case {addr}: goto L{addr};").unwrap();
+    }
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "end:").unwrap();
+    writeln!(out, "    return;").unwrap();
+    writeln!(out, "}}").unwrap();
+    out
+}
+
+/// The C statement for the instruction at `addr`.
+///
+/// A literal followed by an instruction that takes it off the stack again is translated
+/// together with it, like the interpreter fuses them, and `PUSH` together with its operand.
+/// The second word is still translated on its own for jumps straight to it.
+fn translate_instruction(code: &[i32], addr: usize) -> String {
+    let inst = code[addr];
+    let next = code.get(addr + 1).copied();
+    if let (PUSH, Some(operand)) = (inst, next) {
+        return format!("push({}); goto {};", c_int(operand), label(code, addr + 2));
+    }
+    if inst >= 0 {
+        let valid_target = (inst as usize) < code.len();
+        let after = label(code, addr + 2);
+        return match (next, next.and_then(condition)) {
+            (Some(JMP), _) if valid_target => format!("need_space(1); goto L{inst};"),
+            (Some(CALL), _) if valid_target => {
+                format!("need_space(1); call({}); goto L{inst};", addr + 2)
+            }
+            (Some(LOAD), _) => {
+                format!("need_space(1); push(memory[memory_address({inst})]); goto {after};")
+            }
+            (Some(STOR), _) => {
+                format!("need_space(1); {{ int32_t v = pop(); memory[memory_address({inst})] = v; }} goto {after};")
+            }
+            (_, Some(cond)) if valid_target => format!(
+                "need_space(1); {{ int32_t a = pop(), b = pop(); if ({cond}) goto L{inst}; }} \
+                 goto {after};"
+            ),
+            _ => format!("push({inst});"),
+        };
+    }
+    if let Some(cond) = condition(inst) {
+        return format!(
+            "{{ size_t t = jump_target(pop()); int32_t a = pop(), b = pop(); \
+             if ({cond}) {{ ip = t; goto dispatch; }} }}"
+        );
+    }
+    match inst {
+        IN => "in();".to_string(),
+        OUT => "out();".to_string(),
+        ADD => "BINARY(wrap((uint32_t)b + (uint32_t)a));".to_string(),
+        SUB => "BINARY(wrap((uint32_t)b - (uint32_t)a));".to_string(),
+        MUL => "BINARY(wrap((uint32_t)b * (uint32_t)a));".to_string(),
+        DIV => format!("BINARY(divide(b, a, {addr}));"),
+        MOD => format!("BINARY(modulo(b, a, {addr}));"),
+        NEG => "UNARY(wrap(0u - (uint32_t)a));".to_string(),
+        INC => "UNARY(wrap((uint32_t)a + 1u));".to_string(),
+        DEC => "UNARY(wrap((uint32_t)a - 1u));".to_string(),
+        AND => "BINARY(b & a);".to_string(),
+        OR => "BINARY(b | a);".to_string(),
+        NOT => "UNARY(~a);".to_string(),
+        XOR => "BINARY(b ^ a);".to_string(),
+        SHL => "BINARY(wrap((uint32_t)b << (a & 31)));".to_string(),
+        SHR => "BINARY(b >> (a & 31));".to_string(),
+        POP => "pop();".to_string(),
+        DUP => "need_space(1); need_size(1); stack[sp] = stack[sp - 1]; sp++;".to_string(),
+        SWP => "{ int32_t a; need_size(2); a = stack[sp - 1]; stack[sp - 1] = stack[sp - 2]; \
+                stack[sp - 2] = a; }"
+            .to_string(),
+        OVR => "need_size(2); push(stack[sp - 2]);".to_string(),
+        LOAD => "{ size_t m = memory_address(pop()); push(memory[m]); }".to_string(),
+        STOR => "{ int32_t m = pop(), v = pop(); memory[memory_address(m)] = v; }".to_string(),
+        JMP => "ip = jump_target(pop()); goto dispatch;".to_string(),
+        CALL => format!(
+            "ip = jump_target(pop()); call({}); goto dispatch;",
+            addr + 1
+        ),
+        RET => "ip = ret(); goto dispatch;".to_string(),
+        // there are no host functions outside the interpreter
+        SYS => "fail(EXIT_HOST, \"unknown host function %d\", pop());".to_string(),
+        EXIT => "status = pop(); goto end;".to_string(),
+        RF => "rf = 1;".to_string(),
+        CRF => "rf = 0;".to_string(),
+        NOP => ";".to_string(),
+        HALT => "goto end;".to_string(),
+        FADD => "BINARY(from_float(to_float(b) + to_float(a)));".to_string(),
+        FSUB => "BINARY(from_float(to_float(b) - to_float(a)));".to_string(),
+        FMUL => "BINARY(from_float(to_float(b) * to_float(a)));".to_string(),
+        FDIV => "BINARY(from_float(to_float(b) / to_float(a)));".to_string(),
+        FNEG => "UNARY(from_float(-to_float(a)));".to_string(),
+        FEQ => "BINARY(to_float(b) == to_float(a));".to_string(),
+        FLT => "BINARY(to_float(b) < to_float(a));".to_string(),
+        FLE => "BINARY(to_float(b) <= to_float(a));".to_string(),
+        ITOF => "UNARY(from_float((float)a));".to_string(),
+        FTOI => "UNARY(float_to_int(to_float(a)));".to_string(),
+        FIN => "push(read_float());".to_string(),
+        FOUT => "write_float(pop());".to_string(),
+        unk => format!(
+            "fail(EXIT_INSTRUCTION, \"unknown instruction 0x%X\", {}u);",
+            unk as u32
+        ),
+    }
+}
+
+/// The C condition under which a conditional jump is taken, `a` being the top of the stack.
+fn condition(inst: i32) -> Option<&'static str> {
+    match inst {
+        JE => Some("a == b"),
+        JNE => Some("a != b"),
+        JG => Some("a > b"),
+        JGE => Some("a >= b"),
+        JL => Some("a < b"),
+        JLE => Some("a <= b"),
+        _ => None,
+    }
+}
+
+/// `v` as a C expression of type `int32_t`, which `INT32_MIN` cannot be written as literally.
+fn c_int(v: i32) -> String {
+    if v == i32::MIN {
+        "INT32_MIN".to_string()
+    } else {
+        v.to_string()
+    }
+}
+
+/// The label of `addr`, which may be just past the end of the program.
+fn label(code: &[i32], addr: usize) -> String {
+    if addr < code.len() {
+        format!("L{addr}")
+    } else {
+        "end".to_string()
+    }
+}
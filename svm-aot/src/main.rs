@@ -0,0 +1,78 @@
+use std::process::ExitCode;
+
+use svm::program::Program;
+use svm::VMConfig;
+
+mod codegen;
+
+const USAGE: &str = "usage: svm-aot [--stack-size N] [--memory-size N] [--growable-stack] \
+                     [--call-stack-size N] [--legacy] [infile] [outfile]
+
+Only programs with 32-bit words can be translated. There are no host functions, so like
+with svm, every SYS fails with the exit code of an unknown host function.";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let (config, files) = match parse_args(&args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{e}");
+            eprintln!("{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let [infile, outfile] = &files[..] else {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    };
+
+    let program = match load_program(infile, &config) {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("unable to load program: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let source = codegen::translate(&program, &config);
+    if let Err(e) = std::fs::write(outfile, source) {
+        eprintln!("unable to write {outfile}: {e}");
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+fn load_program(filename: &str, config: &VMConfig) -> std::io::Result<Program> {
+    if config.legacy_format {
+        Program::load_legacy(filename)
+    } else {
+        Program::load(filename)
+    }
+}
+
+/// Parses the VM options, which mean the same as for `svm`, and the file names.
+fn parse_args(args: &[String]) -> Result<(VMConfig, Vec<String>), String> {
+    let mut config = VMConfig::default();
+    let mut files = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--stack-size" => config.stack_size = parse_value(arg, args.next())?,
+            "--memory-size" => config.memory_size = parse_value(arg, args.next())?,
+            "--growable-stack" => config.growable_stack = true,
+            "--call-stack-size" => config.call_stack_size = parse_value(arg, args.next())?,
+            "--legacy" => config.legacy_format = true,
+            _ if arg.starts_with("--") => return Err(format!("unknown option '{arg}'")),
+            _ => files.push(arg.clone()),
+        }
+    }
+    Ok((config, files))
+}
+
+fn parse_value<T: std::str::FromStr>(option: &str, value: Option<&String>) -> Result<T, String> {
+    let value = value.ok_or(format!("missing value for '{option}'"))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{value}' for '{option}'"))
+}
@@ -0,0 +1,577 @@
+/*
+ * Runtime support for programs translated by svm-aot, matching the svm interpreter with its
+ * standard I/O backend. The translator defines PROGRAM_LEN, STACK_SIZE, MEMORY_SIZE,
+ * GROWABLE_STACK, MAX_STACK_SIZE, CALL_STACK_SIZE, DATA_LEN and DATA, the initial contents of
+ * memory as a list of initializers, MAX_EXIT_STATUS and the EXIT_ codes of svm for each class
+ * of errors before this file and appends run() after it.
+ */
+
+#include <math.h>
+#include <stdarg.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+/* svm itself would abort. */
+#define EXIT_OUT_OF_MEMORY 1
+
+static int32_t *stack;
+static size_t stack_len;
+static size_t sp;
+static int32_t *memory;
+static size_t call_stack[CALL_STACK_SIZE + 1];
+static size_t call_depth;
+static int rf;
+/* The status passed to EXIT. */
+static int status;
+/* C does not allow empty arrays. */
+static const int32_t data[DATA_LEN + 1] = { DATA 0 };
+
+/* The unread rest of the last line read from stdin. */
+static char *line;
+static size_t line_cap;
+static size_t line_len;
+static size_t line_pos;
+
+/* Stops the program with one of the EXIT_ codes after printing a message. */
+static void fail(int code, const char *fmt, ...)
+{
+    va_list args;
+    fflush(stdout);
+    fputs("svm: ", stderr);
+    va_start(args, fmt);
+    vfprintf(stderr, fmt, args);
+    va_end(args);
+    fputc('\n', stderr);
+    exit(code);
+}
+
+static inline int32_t wrap(uint32_t v)
+{
+    int32_t r;
+    memcpy(&r, &v, sizeof r);
+    return r;
+}
+
+/* Floating-point instructions work on the bits of cells. */
+static inline float to_float(int32_t v)
+{
+    float f;
+    memcpy(&f, &v, sizeof f);
+    return f;
+}
+
+static inline int32_t from_float(float f)
+{
+    int32_t v;
+    memcpy(&v, &f, sizeof v);
+    return v;
+}
+
+/* Rounds toward zero, saturating like Rust's `as`, with NaN becoming 0. */
+static inline int32_t float_to_int(float f)
+{
+    if (isnan(f)) {
+        return 0;
+    }
+    if (f >= 2147483648.0f) {
+        return INT32_MAX;
+    }
+    if (f <= -2147483648.0f) {
+        return INT32_MIN;
+    }
+    return (int32_t)f;
+}
+
+static inline void *alloc_cells(size_t n)
+{
+    void *p = calloc(n ? n : 1, sizeof(int32_t));
+    if (!p) {
+        fail(EXIT_OUT_OF_MEMORY, "out of memory");
+    }
+    return p;
+}
+
+/* Grows a growable stack to have room for `min` more values. */
+static inline int grow_stack(size_t min)
+{
+    size_t needed = sp + min;
+    size_t size;
+    if (!GROWABLE_STACK || needed > (size_t)MAX_STACK_SIZE) {
+        return 0;
+    }
+    size = stack_len * 2 > needed ? stack_len * 2 : needed;
+    if (size > (size_t)MAX_STACK_SIZE) {
+        size = MAX_STACK_SIZE;
+    }
+    stack = realloc(stack, size * sizeof *stack);
+    if (!stack) {
+        fail(EXIT_OUT_OF_MEMORY, "out of memory");
+    }
+    memset(stack + stack_len, 0, (size - stack_len) * sizeof *stack);
+    stack_len = size;
+    return 1;
+}
+
+static inline void need_space(size_t n)
+{
+    if (stack_len - sp < n && !grow_stack(n)) {
+        fail(EXIT_STACK, "stack overflow");
+    }
+}
+
+static inline void need_size(size_t n)
+{
+    if (sp < n) {
+        fail(EXIT_STACK, "stack underflow");
+    }
+}
+
+static inline void push(int32_t v)
+{
+    need_space(1);
+    stack[sp++] = v;
+}
+
+static inline int32_t pop(void)
+{
+    need_size(1);
+    return stack[--sp];
+}
+
+static inline size_t memory_address(int32_t addr)
+{
+    uint64_t a = (uint64_t)(int64_t)addr;
+    if (a >= (uint64_t)MEMORY_SIZE) {
+        fail(EXIT_MEMORY, "invalid memory address");
+    }
+    return (size_t)a;
+}
+
+static inline size_t jump_target(int32_t addr)
+{
+    if (addr < 0 || (uint64_t)addr >= (uint64_t)PROGRAM_LEN) {
+        fail(EXIT_JUMP, "invalid jump target %d", addr);
+    }
+    return (size_t)addr;
+}
+
+static inline void call(size_t addr)
+{
+    if (call_depth >= (size_t)CALL_STACK_SIZE) {
+        fail(EXIT_CALL_STACK, "call stack overflow");
+    }
+    call_stack[call_depth++] = addr;
+}
+
+static inline size_t ret(void)
+{
+    if (call_depth == 0) {
+        fail(EXIT_CALL_STACK, "return without call");
+    }
+    return call_stack[--call_depth];
+}
+
+static inline int32_t divide(int32_t b, int32_t a, size_t ip)
+{
+    if (a == 0) {
+        fail(EXIT_ARITHMETIC, "division by zero at %zu", ip);
+    }
+    if (b == INT32_MIN && a == -1) {
+        fail(EXIT_ARITHMETIC, "arithmetic overflow at %zu", ip);
+    }
+    return b / a;
+}
+
+static inline int32_t modulo(int32_t b, int32_t a, size_t ip)
+{
+    if (a == 0) {
+        fail(EXIT_ARITHMETIC, "division by zero at %zu", ip);
+    }
+    if (a == -1) {
+        return 0;
+    }
+    return b % a;
+}
+
+/* The length of the UTF-8 sequence starting at `s`, or 0 if it is invalid. */
+static inline size_t utf8_len(const unsigned char *s, size_t n)
+{
+    size_t len, i;
+    uint32_t c;
+    if (s[0] < 0x80) {
+        return 1;
+    } else if ((s[0] & 0xE0) == 0xC0) {
+        len = 2;
+        c = s[0] & 0x1F;
+    } else if ((s[0] & 0xF0) == 0xE0) {
+        len = 3;
+        c = s[0] & 0x0F;
+    } else if ((s[0] & 0xF8) == 0xF0) {
+        len = 4;
+        c = s[0] & 0x07;
+    } else {
+        return 0;
+    }
+    if (len > n) {
+        return 0;
+    }
+    for (i = 1; i < len; i++) {
+        if ((s[i] & 0xC0) != 0x80) {
+            return 0;
+        }
+        c = c << 6 | (s[i] & 0x3F);
+    }
+    /* reject overlong encodings, surrogates and code points past U+10FFFF */
+    if ((len == 2 && c < 0x80) || (len == 3 && c < 0x800) || (len == 4 && c < 0x10000) ||
+        (c >= 0xD800 && c <= 0xDFFF) || c > 0x10FFFF) {
+        return 0;
+    }
+    return len;
+}
+
+/* Reads a line from stdin into the pending buffer, returning 0 at the end of input. */
+static inline int read_line(void)
+{
+    size_t i, n;
+    int c;
+    line_len = 0;
+    line_pos = 0;
+    while ((c = getchar()) != EOF) {
+        if (line_len == line_cap) {
+            line_cap = line_cap ? line_cap * 2 : 128;
+            line = realloc(line, line_cap);
+            if (!line) {
+                fail(EXIT_OUT_OF_MEMORY, "out of memory");
+            }
+        }
+        line[line_len++] = (char)c;
+        if (c == '\n') {
+            break;
+        }
+    }
+    if (ferror(stdin)) {
+        fail(EXIT_IO, "io error");
+    }
+    for (i = 0; i < line_len; i += n) {
+        n = utf8_len((const unsigned char *)line + i, line_len - i);
+        if (n == 0) {
+            fail(EXIT_IO, "io error");
+        }
+    }
+    return line_len != 0;
+}
+
+static inline int is_space(char c)
+{
+    return c == ' ' || c == '\t' || c == '\n' || c == '\r' || c == '\v' || c == '\f';
+}
+
+/* Takes the rest of a line, trimmed, after a `?` prompt if there is nothing left. */
+static inline void read_text(const char **start, const char **end)
+{
+    const char *s, *e;
+    if (line_pos == line_len) {
+        fputc('?', stdout);
+        fflush(stdout);
+        if (!read_line()) {
+            fail(EXIT_IO, "io error");
+        }
+    }
+    s = line + line_pos;
+    e = line + line_len;
+    line_pos = line_len;
+    while (s < e && is_space(*s)) {
+        s++;
+    }
+    while (e > s && is_space(e[-1])) {
+        e--;
+    }
+    *start = s;
+    *end = e;
+}
+
+/* Reads the rest of a line as a number. */
+static inline int32_t read_number(void)
+{
+    const char *s, *end;
+    int negative = 0;
+    int64_t v = 0;
+    read_text(&s, &end);
+    if (s < end && (*s == '+' || *s == '-')) {
+        negative = *s == '-';
+        s++;
+    }
+    if (s == end) {
+        fail(EXIT_IO, "io error");
+    }
+    for (; s < end; s++) {
+        if (*s < '0' || *s > '9') {
+            fail(EXIT_IO, "io error");
+        }
+        v = v * 10 + (*s - '0');
+        if (v > (int64_t)INT32_MAX + negative) {
+            fail(EXIT_IO, "io error");
+        }
+    }
+    return (int32_t)(negative ? -v : v);
+}
+
+static inline int matches_word(const char *s, const char *end, const char *word)
+{
+    size_t n = strlen(word), i;
+    if ((size_t)(end - s) != n) {
+        return 0;
+    }
+    for (i = 0; i < n; i++) {
+        if ((s[i] | 0x20) != word[i]) {
+            return 0;
+        }
+    }
+    return 1;
+}
+
+static inline const char *skip_digits(const char *s, const char *end)
+{
+    while (s < end && *s >= '0' && *s <= '9') {
+        s++;
+    }
+    return s;
+}
+
+/* Reads the rest of a line as a float, accepting what Rust's `f32::from_str` does. */
+static inline int32_t read_float(void)
+{
+    const char *s, *end, *p, *q;
+    char buf[128];
+    char *copy = buf;
+    size_t n;
+    float f;
+    read_text(&s, &end);
+    p = s;
+    if (p < end && (*p == '+' || *p == '-')) {
+        p++;
+    }
+    if (!matches_word(p, end, "inf") && !matches_word(p, end, "infinity") &&
+        !matches_word(p, end, "nan")) {
+        q = skip_digits(p, end);
+        n = (size_t)(q - p);
+        if (q < end && *q == '.') {
+            p = q + 1;
+            q = skip_digits(p, end);
+            n += (size_t)(q - p);
+        }
+        if (n == 0) {
+            fail(EXIT_IO, "io error");
+        }
+        if (q < end && (*q == 'e' || *q == 'E')) {
+            p = q + 1;
+            if (p < end && (*p == '+' || *p == '-')) {
+                p++;
+            }
+            q = skip_digits(p, end);
+            if (q == p) {
+                fail(EXIT_IO, "io error");
+            }
+        }
+        if (q != end) {
+            fail(EXIT_IO, "io error");
+        }
+    }
+    n = (size_t)(end - s);
+    if (n >= sizeof buf) {
+        copy = malloc(n + 1);
+        if (!copy) {
+            fail(EXIT_OUT_OF_MEMORY, "out of memory");
+        }
+    }
+    memcpy(copy, s, n);
+    copy[n] = '\0';
+    f = strtof(copy, NULL);
+    if (copy != buf) {
+        free(copy);
+    }
+    return from_float(f);
+}
+
+/* Reads a character as a code point, or -1 at the end of input. */
+static inline int32_t read_char(void)
+{
+    const unsigned char *s;
+    size_t n, i;
+    uint32_t c;
+    if (line_pos == line_len) {
+        fflush(stdout);
+        if (!read_line()) {
+            return -1;
+        }
+    }
+    s = (const unsigned char *)line + line_pos;
+    n = utf8_len(s, line_len - line_pos);
+    line_pos += n;
+    if (n == 1) {
+        return s[0];
+    }
+    c = s[0] & (0xFF >> (n + 1));
+    for (i = 1; i < n; i++) {
+        c = c << 6 | (s[i] & 0x3F);
+    }
+    return (int32_t)c;
+}
+
+static inline void write_number(int32_t v)
+{
+    printf("%d\n", (int)v);
+}
+
+/*
+ * Writes a float like Rust's `Display`: the shortest digits that read back as the same float,
+ * without an exponent.
+ */
+static inline void write_float(int32_t v)
+{
+    float f = to_float(v);
+    /* more than enough for the exact value of any float */
+    char exact[168], digits[10], buf[32];
+    int p, n, exp, i, up, tries;
+    if (isnan(f)) {
+        puts("NaN");
+        return;
+    }
+    if (signbit(f)) {
+        putchar('-');
+        f = -f;
+    }
+    if (isinf(f)) {
+        puts("inf");
+        return;
+    }
+    if (f == 0.0f) {
+        puts("0");
+        return;
+    }
+    /* d.ddd...e±x, with the digits moved together */
+    snprintf(exact, sizeof exact, "%.150e", (double)f);
+    memmove(exact + 1, exact + 2, 150);
+    for (p = 1; p <= 9; p++) {
+        /*
+         * try the p digits below and above the exact value, the nearer first and up on ties
+         * like Rust, since near powers of two only the farther one may read back
+         */
+        up = exact[p] >= '5';
+        for (tries = 0; tries < 2; tries++, up = !up) {
+            memcpy(digits, exact, (size_t)p);
+            exp = atoi(strchr(exact, 'e') + 1);
+            if (up) {
+                for (i = p - 1; i >= 0 && digits[i] == '9'; i--) {
+                    digits[i] = '0';
+                }
+                if (i < 0) {
+                    digits[0] = '1';
+                    exp++;
+                } else {
+                    digits[i]++;
+                }
+            }
+            snprintf(buf, sizeof buf, "%.*se%d", p, digits, exp - (p - 1));
+            if (strtof(buf, NULL) == f) {
+                goto found;
+            }
+        }
+    }
+found:
+    n = p;
+    while (n > 1 && digits[n - 1] == '0') {
+        n--;
+    }
+    if (exp < 0) {
+        fputs("0.", stdout);
+        for (i = -1; i > exp; i--) {
+            putchar('0');
+        }
+        fwrite(digits, 1, (size_t)n, stdout);
+    } else {
+        for (i = 0; i <= exp || i < n; i++) {
+            if (i == exp + 1) {
+                putchar('.');
+            }
+            putchar(i < n ? digits[i] : '0');
+        }
+    }
+    putchar('\n');
+}
+
+/* Writes a code point as UTF-8, replacing invalid ones with U+FFFD. */
+static inline void write_char(int32_t v)
+{
+    uint32_t c = (uint32_t)v;
+    if (c > 0x10FFFF || (c >= 0xD800 && c <= 0xDFFF)) {
+        c = 0xFFFD;
+    }
+    if (c < 0x80) {
+        putchar((int)c);
+    } else if (c < 0x800) {
+        putchar((int)(0xC0 | c >> 6));
+        putchar((int)(0x80 | (c & 0x3F)));
+    } else if (c < 0x10000) {
+        putchar((int)(0xE0 | c >> 12));
+        putchar((int)(0x80 | (c >> 6 & 0x3F)));
+        putchar((int)(0x80 | (c & 0x3F)));
+    } else {
+        putchar((int)(0xF0 | c >> 18));
+        putchar((int)(0x80 | (c >> 12 & 0x3F)));
+        putchar((int)(0x80 | (c >> 6 & 0x3F)));
+        putchar((int)(0x80 | (c & 0x3F)));
+    }
+}
+
+/* IN and OUT, which read and write characters instead of numbers in raw mode. */
+static inline void in(void)
+{
+    push(rf ? read_char() : read_number());
+}
+
+static inline void out(void)
+{
+    int32_t v = pop();
+    if (rf) {
+        write_char(v);
+    } else {
+        write_number(v);
+    }
+}
+
+#define BINARY(expr)                                                                              \
+    do {                                                                                          \
+        int32_t a, b;                                                                             \
+        need_size(2);                                                                             \
+        a = stack[sp - 1];                                                                        \
+        b = stack[sp - 2];                                                                        \
+        stack[sp - 2] = (expr);                                                                   \
+        sp--;                                                                                     \
+    } while (0)
+
+#define UNARY(expr)                                                                               \
+    do {                                                                                          \
+        int32_t a;                                                                                \
+        need_size(1);                                                                             \
+        a = stack[sp - 1];                                                                        \
+        stack[sp - 1] = (expr);                                                                   \
+    } while (0)
+
+static void run(void);
+
+int main(void)
+{
+    stack_len = STACK_SIZE;
+    stack = alloc_cells(stack_len);
+    memory = alloc_cells(MEMORY_SIZE);
+    memcpy(memory, data, DATA_LEN * sizeof *memory);
+    run();
+    if (fflush(stdout) != 0) {
+        fail(EXIT_IO, "io error");
+    }
+    /* like svm, statuses outside the range left to programs exit with its largest status */
+    return status < 0 || status > MAX_EXIT_STATUS ? MAX_EXIT_STATUS : status;
+}
@@ -0,0 +1,302 @@
+//! Translates programs to C, compiles them with the system C compiler and checks that they
+//! behave like the interpreter on the same input.
+//!
+//! Skipped if there is no `cc` on the path.
+
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use svm::instructions::*;
+use svm::io::Io;
+use svm::program::Program;
+use svm::VM;
+
+/// The standard I/O backend of `svm` over in-memory input and output.
+#[derive(Default)]
+struct LineIo {
+    input: VecDeque<String>,
+    pending: VecDeque<char>,
+    output: String,
+}
+
+impl LineIo {
+    fn new(input: &str) -> Self {
+        LineIo {
+            input: input.split_inclusive('\n').map(str::to_string).collect(),
+            ..LineIo::default()
+        }
+    }
+
+    fn read_line(&mut self) -> bool {
+        match self.input.pop_front() {
+            Some(line) => {
+                self.pending.extend(line.chars());
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Io for LineIo {
+    fn read_number(&mut self) -> io::Result<i64> {
+        self.read_float()?
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn read_float(&mut self) -> io::Result<String> {
+        if self.pending.is_empty() {
+            self.output.push('?');
+            if !self.read_line() {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+        let line: String = self.pending.drain(..).collect();
+        Ok(line.trim().to_string())
+    }
+
+    fn read_char(&mut self) -> io::Result<Option<char>> {
+        if self.pending.is_empty() {
+            self.read_line();
+        }
+        Ok(self.pending.pop_front())
+    }
+
+    fn write_number(&mut self, v: i64) -> io::Result<()> {
+        self.output.push_str(&format!("{v}\n"));
+        Ok(())
+    }
+
+    fn write_float(&mut self, v: &str) -> io::Result<()> {
+        self.output.push_str(&format!("{v}\n"));
+        Ok(())
+    }
+
+    fn write_char(&mut self, c: char) -> io::Result<()> {
+        self.output.push(c);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The output of a run and the exit code of `svm` for it.
+type Outcome = (String, i32);
+
+fn interpret(program: &Path, input: &str) -> Outcome {
+    let mut vm = VM::with_io(LineIo::new(input));
+    vm.load(program.to_str().unwrap()).unwrap();
+    let code = match vm.run() {
+        Ok(reason) => reason.exit_code().unwrap(),
+        Err(e) => e.kind.exit_code(),
+    };
+    (vm.into_io().output, code as i32)
+}
+
+fn compile(program: &Path, dir: &Path) -> PathBuf {
+    let name = program.file_name().unwrap().to_str().unwrap();
+    let source = dir.join(format!("{name}.c"));
+    let exe = dir.join(format!("{name}.out"));
+    let status = Command::new(env!("CARGO_BIN_EXE_svm-aot"))
+        .arg(program)
+        .arg(&source)
+        .status()
+        .unwrap();
+    assert!(status.success(), "svm-aot failed on {name}");
+    let status = Command::new("cc")
+        .arg("-O1")
+        .arg("-o")
+        .arg(&exe)
+        .arg(&source)
+        .status()
+        .unwrap();
+    assert!(status.success(), "cc failed on {name}");
+    exe
+}
+
+fn execute(exe: &Path, input: &str) -> Outcome {
+    let mut child = Command::new(exe)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    // the program may exit without reading everything
+    child.stdin.take().unwrap().write_all(input.as_bytes()).ok();
+    let output = child.wait_with_output().unwrap();
+    (
+        String::from_utf8(output.stdout).unwrap(),
+        output.status.code().unwrap(),
+    )
+}
+
+fn check(program: &Path, inputs: &[&str], dir: &Path) {
+    let exe = compile(program, dir);
+    for input in inputs {
+        assert_eq!(
+            execute(&exe, input),
+            interpret(program, input),
+            "{} on input {input:?}",
+            program.display()
+        );
+    }
+}
+
+fn has_cc() -> bool {
+    Command::new("cc")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("svm-aot-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn examples() {
+    if !has_cc() {
+        eprintln!("no C compiler, skipping");
+        return;
+    }
+    let sequence: String = std::iter::once(300)
+        .chain((0..300).map(|i| i * 7919 % 10007))
+        .map(|v| format!("{v}\n"))
+        .collect();
+    let text = "the quick brown fox\njumps over\tthe lazy dög ✓\n\n  end";
+    let cases: &[(&str, &[&str])] = &[
+        ("hello", &[""]),
+        ("sum", &["3\n4\n", "-2147483648\n-1\n", "1\n", "x\n"]),
+        ("gcd", &["48\n18\n", "7\n0\n", "0\n0\n"]),
+        ("negtest", &["-5\n", "5\n"]),
+        ("fib", &["0\n", "1\n", "20\n"]),
+        ("echo", &["", text]),
+        ("wc", &["", text]),
+        ("isort", &[&sequence, "0\n", "3\n1\n2\n"]),
+        ("ssort", &[&sequence, "0\n", "3\n1\n2\n"]),
+        ("strings", &[""]),
+        (
+            "circle",
+            &[
+                "1\n", "2.5\n", "-0.5\n", "1e20\n", "nan\n", "-inf\n", "x\n", "0.1\n", "1e-30\n",
+            ],
+        ),
+    ];
+
+    let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("../examples");
+    let dir = scratch_dir("examples");
+    for (name, inputs) in cases {
+        check(&examples.join(name).join(name), inputs, &dir);
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+/// Reads two numbers and prints which of the conditional jumps on them are taken, with both
+/// literal and computed targets.
+fn comparisons() -> Vec<i32> {
+    let mut code = vec![IN, 0, STOR, IN, 1, STOR];
+    for jump in [JE, JNE, JG, JGE, JL, JLE] {
+        for computed in [false, true] {
+            let start = code.len() as i32;
+            code.extend([1, LOAD, 0, LOAD]);
+            // the taken branch starts 5 words after the jump
+            let target = start + 10 + computed as i32 * 2;
+            code.push(target);
+            if computed {
+                code.extend([0, ADD]);
+            }
+            code.extend([jump, 0, OUT, target + 2, JMP, 1, OUT]);
+        }
+    }
+    code
+}
+
+#[test]
+fn programs() {
+    if !has_cc() {
+        eprintln!("no C compiler, skipping");
+        return;
+    }
+    let programs: &[(&str, Vec<i32>)] = &[
+        ("division_by_zero", vec![IN, IN, DIV, OUT]),
+        ("overflow", vec![IN, IN, DIV, OUT, IN, IN, MOD, OUT]),
+        ("underflow", vec![IN, ADD]),
+        ("jump", vec![IN, JMP, 1, OUT]),
+        ("memory", vec![5, IN, STOR, IN, LOAD, OUT]),
+        ("unknown", vec![1, OUT, -77]),
+        ("ret", vec![RET]),
+        ("recursion", vec![0, CALL]),
+        ("sys", vec![IN, SYS]),
+        ("exit", vec![IN, EXIT, 1, OUT]),
+        ("overflow_stack", vec![1, 0, JMP]),
+        ("comparisons", comparisons()),
+        // jumps into operands run them as instructions, and a PUSH at the end has none
+        (
+            "push",
+            vec![PUSH, -7, OUT, PUSH, i32::MIN, OUT, IN, JMP, PUSH],
+        ),
+        (
+            "floats",
+            vec![
+                FIN, FIN, OVR, OVR, FDIV, DUP, FOUT, FTOI, OUT, OVR, OVR, FSUB, FNEG, FOUT, OVR,
+                OVR, FMUL, FOUT, OVR, OVR, FLT, OUT, OVR, OVR, FEQ, OUT, FLE, OUT, IN, ITOF, FOUT,
+            ],
+        ),
+        (
+            "raw",
+            vec![
+                RF, IN, OUT, 9731, OUT, 55296, OUT, IN, NEG, OUT, CRF, IN, OUT,
+            ],
+        ),
+    ];
+    let inputs = [
+        "",
+        "1\n0\n",
+        "-2147483648\n-1\n5\n-1\n",
+        "2\n",
+        "-1\n",
+        "1024\n3\n",
+        "1023\n1023\n",
+        "-3\n4\n",
+        "é\n",
+        "1.5\n-0.25\n16777217\n",
+        "nan\nINF\n-7\n",
+        "1e-45\n3e38\n-2147483647\n",
+        // subnormals, signed zeros, infinities and large exponents
+        "-0.0\n0\n0\n",
+        "-0\n-1\n-0\n",
+        "1.4e-45\n-2\n2147483647\n",
+        "1e-40\n1e30\n16777216\n",
+        "1.1754942e-38\n7e-39\n-16777217\n",
+        "3.4028235e38\n0.5\n2147483584\n",
+        "1e39\n-1e-46\n-2147483648\n",
+        "-inf\nNaN\n1\n",
+        "+infinity\n-1e38\n0\n",
+        "123456789e-20\n9.999999e9\n0\n",
+        ".5\n5.\n1\n",
+        "1e\n",
+        "0x10\n",
+        "4\n",
+        "8\n",
+        "63\n",
+        "64\n",
+    ];
+
+    let dir = scratch_dir("errors");
+    for (name, code) in programs {
+        let path = dir.join(name);
+        let file = std::fs::File::create(&path).unwrap();
+        Program::new(code.clone()).write(file).unwrap();
+        check(&path, &inputs, &dir);
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -1,125 +1,233 @@
 use std::collections::HashMap;
 
 use svm::instructions::*;
+use svm::program::Program;
+use svm::source_map::{SourceLocation, SourceMap};
+use svm::symbols::SymbolTable;
+use svm::Word;
 
 use crate::token::Token;
 
+/// Where assembled words go: the program itself or the initial contents of memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Segment {
+    Code,
+    Data,
+}
+
 struct LabelInfo {
     pub addr: Option<usize>,
-    pub refs: Vec<usize>,
+    /// The segment the label was defined in, which `addr` is an address into.
+    pub segment: Segment,
+    pub refs: Vec<(Segment, usize)>,
 }
 
 impl LabelInfo {
     fn new() -> Self {
         LabelInfo {
             addr: None,
+            segment: Segment::Code,
             refs: Vec::new(),
         }
     }
 }
 
-pub fn generate<'s>(tokens: &[Token<'s>]) -> Vec<i32> {
-    let mut code: Vec<i32> = Vec::new();
+/// Assembles `tokens` read from `file` into a program with words of type `W`, recording where
+/// every code address came from.
+pub fn generate<'s, W: Word>(tokens: &[(Token<'s>, SourceLocation)], file: &str) -> Program<W> {
+    let mut program = Program::default();
+    let mut code: Vec<W> = Vec::new();
+    let mut data: Vec<W> = Vec::new();
+    let mut segment = Segment::Code;
     let mut labels: HashMap<&'s str, LabelInfo> = HashMap::new();
+    let mut source_map = SourceMap::new(file);
 
-    for tk in tokens {
+    for (tk, location) in tokens {
+        let start = code.len();
+        let words = match segment {
+            Segment::Code => &mut code,
+            Segment::Data => &mut data,
+        };
         match tk {
             Token::LabelDef(name) => {
                 if labels.contains_key(name) && labels[name].addr.is_some() {
                     panic!("duplicate label");
                 }
-                labels.entry(name).or_insert_with(LabelInfo::new).addr = Some(code.len());
+                let info = labels.entry(name).or_insert_with(LabelInfo::new);
+                info.addr = Some(words.len());
+                info.segment = segment;
             }
             Token::LabelRef(name) => {
-                code.push(0);
+                words.push(W::default());
                 labels
                     .entry(name)
                     .or_insert_with(LabelInfo::new)
                     .refs
-                    .push(code.len() - 1);
+                    .push((segment, words.len() - 1));
             }
+            Token::StackSize(size) => program.stack_size = Some(*size),
+            Token::MemorySize(size) => program.memory_size = Some(*size),
+            Token::Code => segment = Segment::Code,
+            Token::Data => segment = Segment::Data,
+            _ if segment == Segment::Data => generate_data(words, tk),
+            Token::Space(_) => panic!("'.space' outside of the data segment"),
             Token::String(s) => generate_string(&mut code, s),
             Token::EscapedString(s) => generate_string(&mut code, s),
             Token::Number(v) => generate_number(&mut code, *v),
+            Token::Float(s) => generate_float(&mut code, s),
             Token::Print => generate_print(&mut code),
-            Token::In => code.push(IN),
-            Token::Out => code.push(OUT),
-            Token::Add => code.push(ADD),
-            Token::Sub => code.push(SUB),
-            Token::Mul => code.push(MUL),
-            Token::Div => code.push(DIV),
-            Token::Mod => code.push(MOD),
-            Token::Neg => code.push(NEG),
-            Token::Inc => code.push(INC),
-            Token::Dec => code.push(DEC),
-            Token::And => code.push(AND),
-            Token::Or => code.push(OR),
-            Token::Not => code.push(NOT),
-            Token::Xor => code.push(XOR),
-            Token::Shl => code.push(SHL),
-            Token::Shr => code.push(SHR),
-            Token::Pop => code.push(POP),
-            Token::Dup => code.push(DUP),
-            Token::Swp => code.push(SWP),
-            Token::Ovr => code.push(OVR),
-            Token::Load => code.push(LOAD),
-            Token::Stor => code.push(STOR),
-            Token::Jmp => code.push(JMP),
-            Token::Je => code.push(JE),
-            Token::Jne => code.push(JNE),
-            Token::Jg => code.push(JG),
-            Token::Jge => code.push(JGE),
-            Token::Jl => code.push(JL),
-            Token::Jle => code.push(JLE),
-            Token::Nop => code.push(NOP),
-            Token::Halt => code.push(HALT),
-            Token::Rf => code.push(RF),
-            Token::Crf => code.push(CRF),
+            Token::In => code.push(IN.into()),
+            Token::Out => code.push(OUT.into()),
+            Token::Add => code.push(ADD.into()),
+            Token::Sub => code.push(SUB.into()),
+            Token::Mul => code.push(MUL.into()),
+            Token::Div => code.push(DIV.into()),
+            Token::Mod => code.push(MOD.into()),
+            Token::Neg => code.push(NEG.into()),
+            Token::Inc => code.push(INC.into()),
+            Token::Dec => code.push(DEC.into()),
+            Token::And => code.push(AND.into()),
+            Token::Or => code.push(OR.into()),
+            Token::Not => code.push(NOT.into()),
+            Token::Xor => code.push(XOR.into()),
+            Token::Shl => code.push(SHL.into()),
+            Token::Shr => code.push(SHR.into()),
+            Token::Pop => code.push(POP.into()),
+            Token::Dup => code.push(DUP.into()),
+            Token::Swp => code.push(SWP.into()),
+            Token::Ovr => code.push(OVR.into()),
+            Token::Load => code.push(LOAD.into()),
+            Token::Stor => code.push(STOR.into()),
+            Token::Jmp => code.push(JMP.into()),
+            Token::Je => code.push(JE.into()),
+            Token::Jne => code.push(JNE.into()),
+            Token::Jg => code.push(JG.into()),
+            Token::Jge => code.push(JGE.into()),
+            Token::Jl => code.push(JL.into()),
+            Token::Jle => code.push(JLE.into()),
+            Token::Nop => code.push(NOP.into()),
+            Token::Halt => code.push(HALT.into()),
+            Token::Call => code.push(CALL.into()),
+            Token::Ret => code.push(RET.into()),
+            Token::Sys => code.push(SYS.into()),
+            Token::Exit => code.push(EXIT.into()),
+            Token::Rf => code.push(RF.into()),
+            Token::Crf => code.push(CRF.into()),
+            Token::FAdd => code.push(FADD.into()),
+            Token::FSub => code.push(FSUB.into()),
+            Token::FMul => code.push(FMUL.into()),
+            Token::FDiv => code.push(FDIV.into()),
+            Token::FNeg => code.push(FNEG.into()),
+            Token::FEq => code.push(FEQ.into()),
+            Token::FLt => code.push(FLT.into()),
+            Token::FLe => code.push(FLE.into()),
+            Token::IToF => code.push(ITOF.into()),
+            Token::FToI => code.push(FTOI.into()),
+            Token::FIn => code.push(FIN.into()),
+            Token::FOut => code.push(FOUT.into()),
+        }
+        for addr in start..code.len() {
+            source_map.insert(addr, *location);
         }
     }
 
-    finalize_labels(&mut code, &mut labels);
-    code.push(NOP);
+    finalize_labels(&mut code, &mut data, &mut labels);
+    code.push(NOP.into());
 
-    code
+    program.code = code;
+    program.data = data;
+    program.source_map = Some(source_map);
+
+    let mut symbols = SymbolTable::new();
+    for (name, info) in labels.iter() {
+        if info.segment == Segment::Code {
+            symbols.insert(name, info.addr.unwrap());
+        }
+    }
+    program.symbols = Some(symbols);
+
+    program
 }
 
-fn finalize_labels<'s>(code: &mut Vec<i32>, labels: &mut HashMap<&'s str, LabelInfo>) {
+fn finalize_labels<W: Word>(code: &mut [W], data: &mut [W], labels: &mut HashMap<&str, LabelInfo>) {
     for (name, info) in labels.iter() {
         if info.addr.is_none() {
             panic!("undefined label: {name}");
         }
-        for r in info.refs.iter() {
-            code[*r] = info.addr.unwrap() as i32;
+        for (segment, r) in info.refs.iter() {
+            let words = match segment {
+                Segment::Code => &mut *code,
+                Segment::Data => &mut *data,
+            };
+            words[*r] = word(info.addr.unwrap() as i64);
         }
     }
 }
 
-fn generate_string(code: &mut Vec<i32>, s: &str) {
-    code.push(0); // is this correct?
+/// Places a value in the data segment. Strings are stored in order and terminated by a 0.
+fn generate_data<W: Word>(data: &mut Vec<W>, tk: &Token) {
+    match tk {
+        Token::Number(v) => data.push(word(*v)),
+        Token::Float(s) => data.push(W::from_float(float::<W>(s))),
+        Token::String(s) => generate_data_string(data, s),
+        Token::EscapedString(s) => generate_data_string(data, s),
+        Token::Space(n) => data.resize(data.len() + n, W::default()),
+        tk => panic!("instruction in the data segment: {tk:?}"),
+    }
+}
+
+fn generate_data_string<W: Word>(data: &mut Vec<W>, s: &str) {
+    data.extend(s.chars().map(|c| W::from(c as i32)));
+    data.push(W::default());
+}
+
+fn generate_string<W: Word>(code: &mut Vec<W>, s: &str) {
+    code.push(W::default()); // is this correct?
     for c in s.chars().rev() {
-        code.push(c as i32);
+        code.push(W::from(c as i32));
     }
 }
 
-fn generate_number(code: &mut Vec<i32>, v: i32) {
-    code.push(v.abs());
-    if v < 0 {
-        code.push(NEG);
+/// Non-negative numbers are pushed by themselves, negative ones would be taken for instructions
+/// so they follow a `PUSH`.
+fn generate_number<W: Word>(code: &mut Vec<W>, v: i64) {
+    generate_literal(code, word(v));
+}
+
+/// Floats are pushed by their bits, which are negative for negative floats.
+fn generate_float<W: Word>(code: &mut Vec<W>, s: &str) {
+    generate_literal(code, W::from_float(float::<W>(s)));
+}
+
+fn generate_literal<W: Word>(code: &mut Vec<W>, v: W) {
+    if !v.is_literal() {
+        code.push(PUSH.into());
     }
+    code.push(v);
 }
 
-fn generate_print(code: &mut Vec<i32>) {
-    code.push(RF);
+fn generate_print<W: Word>(code: &mut Vec<W>) {
+    code.push(RF.into());
     let prn = code.len();
     let end = prn + 7;
-    code.push(DUP);
-    code.push(0);
-    code.push(end as i32);
-    code.push(JE);
-    code.push(OUT);
-    code.push(prn as i32);
-    code.push(JMP);
-    code.push(CRF);
-    code.push(POP);
+    code.push(DUP.into());
+    code.push(W::default());
+    code.push(word(end as i64));
+    code.push(JE.into());
+    code.push(OUT.into());
+    code.push(word(prn as i64));
+    code.push(JMP.into());
+    code.push(CRF.into());
+    code.push(POP.into());
+}
+
+/// `v` as a word, which it has to fit in.
+fn word<W: Word>(v: i64) -> W {
+    W::try_from(v).unwrap_or_else(|_| panic!("{v} does not fit in a {}-bit word", W::BITS))
+}
+
+/// `s` as a float of the word size, rounded to the nearest one.
+fn float<W: Word>(s: &str) -> W::Float {
+    s.parse()
+        .unwrap_or_else(|_| panic!("unable to parse number '{s}'"))
 }
@@ -1,53 +1,71 @@
+use svm::source_map::SourceLocation;
+
 use crate::token::Token;
 
-pub fn tokenize(source: &str) -> Vec<Token> {
+pub fn tokenize(source: &str) -> Vec<(Token<'_>, SourceLocation)> {
     Lexer::new(source).tokenize()
 }
 
 pub struct Lexer<'s> {
     source: &'s str,
     current: usize,
+    /// The line of `current` and the offset that line starts at.
+    line: usize,
+    line_start: usize,
 }
 
 impl<'s> Lexer<'s> {
     pub fn new(source: &'s str) -> Self {
-        Lexer { source, current: 0 }
+        Lexer {
+            source,
+            current: 0,
+            line: 1,
+            line_start: 0,
+        }
     }
 
-    pub fn tokenize(self) -> Vec<Token<'s>> {
+    pub fn tokenize(self) -> Vec<(Token<'s>, SourceLocation)> {
         self.collect()
     }
 
-    fn next_token(&mut self) -> Option<Token<'s>> {
+    fn next_token(&mut self) -> Option<(Token<'s>, SourceLocation)> {
+        self.skip_whitespace();
+        let location = self.location();
+        self.peek()?;
+        Some((self.next_token_here(), location))
+    }
+
+    fn skip_whitespace(&mut self) {
         while let Some(c) = self.peek() {
             if c.is_ascii_whitespace() {
                 self.consume();
-                continue;
-            }
-            if c == ';' {
+            } else if c == ';' {
                 self.consume_until_newline();
                 self.consume();
-                continue;
+            } else {
+                return;
             }
+        }
+    }
 
-            if c.is_ascii_alphabetic() {
-                return Some(self.tokenize_instruction());
-            }
-            if c.is_ascii_digit() {
-                return Some(self.tokenize_number());
-            }
-            if c == ':' {
-                return Some(self.tokenize_label_def());
-            }
-            if c == '@' {
-                return Some(self.tokenize_label_ref());
-            }
-            if c == '"' {
-                return Some(self.tokenize_string());
-            }
-            panic!("unexpected char: {c}");
+    fn location(&self) -> SourceLocation {
+        SourceLocation {
+            line: self.line,
+            column: self.source[self.line_start..self.current].chars().count() + 1,
+        }
+    }
+
+    fn next_token_here(&mut self) -> Token<'s> {
+        match self.peek() {
+            Some(c) if c.is_ascii_alphabetic() => self.tokenize_instruction(),
+            Some(c) if c.is_ascii_digit() || c == '-' => self.tokenize_number(),
+            Some(':') => self.tokenize_label_def(),
+            Some('@') => self.tokenize_label_ref(),
+            Some('"') => self.tokenize_string(),
+            Some('.') => self.tokenize_directive(),
+            Some(c) => panic!("unexpected char: {c}"),
+            None => unreachable!("no token at the end of the source"),
         }
-        None
     }
 
     fn peek(&self) -> Option<char> {
@@ -57,6 +75,10 @@ impl<'s> Lexer<'s> {
     fn consume(&mut self) -> Option<char> {
         if let Some(c) = self.source[self.current..].chars().next() {
             self.current += c.len_utf8();
+            if c == '\n' {
+                self.line += 1;
+                self.line_start = self.current;
+            }
             Some(c)
         } else {
             None
@@ -105,9 +127,15 @@ impl<'s> Lexer<'s> {
         self.consume_until_whitespace();
         let end = self.current;
         let slice = &self.source[start..end];
+        if slice.contains('.') {
+            if slice.parse::<f64>().is_err() {
+                panic!("unable to parse number '{slice}'");
+            }
+            return Token::Float(slice);
+        }
         let num = slice
             .parse()
-            .expect(&format!("unable to parse number '{slice}'"));
+            .unwrap_or_else(|_| panic!("unable to parse number '{slice}'"));
         Token::Number(num)
     }
 
@@ -127,6 +155,36 @@ impl<'s> Lexer<'s> {
         }
     }
 
+    fn tokenize_directive(&mut self) -> Token<'s> {
+        self.consume(); // the '.'
+        let start = self.current;
+        self.consume_until_whitespace();
+        let end = self.current;
+        let name = &self.source[start..end];
+
+        match name.to_lowercase().as_str() {
+            "code" => return Token::Code,
+            "data" => return Token::Data,
+            _ => {}
+        }
+
+        self.consume_until(|c| !c.is_ascii_whitespace());
+        let start = self.current;
+        self.consume_until_whitespace();
+        let end = self.current;
+        let slice = &self.source[start..end];
+        let value = slice
+            .parse()
+            .unwrap_or_else(|_| panic!("unable to parse value '{slice}' of directive '.{name}'"));
+
+        match name.to_lowercase().as_str() {
+            "stack" => Token::StackSize(value),
+            "memory" => Token::MemorySize(value),
+            "space" => Token::Space(value),
+            _ => panic!("invalid directive: '.{name}'"),
+        }
+    }
+
     fn tokenize_instruction(&mut self) -> Token<'s> {
         let start = self.current;
         self.consume_until_whitespace();
@@ -165,15 +223,31 @@ impl<'s> Lexer<'s> {
             "JLE" => Token::Jle,
             "NOP" => Token::Nop,
             "HALT" => Token::Halt,
+            "CALL" => Token::Call,
+            "RET" => Token::Ret,
+            "SYS" => Token::Sys,
+            "EXIT" => Token::Exit,
             "RF" => Token::Rf,
             "CRF" => Token::Crf,
+            "FADD" => Token::FAdd,
+            "FSUB" => Token::FSub,
+            "FMUL" => Token::FMul,
+            "FDIV" => Token::FDiv,
+            "FNEG" => Token::FNeg,
+            "FEQ" => Token::FEq,
+            "FLT" => Token::FLt,
+            "FLE" => Token::FLe,
+            "ITOF" => Token::IToF,
+            "FTOI" => Token::FToI,
+            "FIN" => Token::FIn,
+            "FOUT" => Token::FOut,
             _ => panic!("invalid instruction: '{slice}'"),
         }
     }
 }
 
 impl<'s> Iterator for Lexer<'s> {
-    type Item = Token<'s>;
+    type Item = (Token<'s>, SourceLocation);
 
     fn next(&mut self) -> Option<Self::Item> {
         self.next_token()
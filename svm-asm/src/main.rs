@@ -1,16 +1,44 @@
-use std::io::Write;
-
 use simplelog::{Config, LevelFilter, TermLogger, TerminalMode};
+use svm::source_map::SourceLocation;
+use svm::symbols::SymbolTable;
+use svm::Word;
+
+use crate::token::Token;
 
 mod codegen;
 mod lexer;
 mod token;
 
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
+    let mut args: Vec<String> = std::env::args().collect();
+
+    // also write the label addresses next to the program for older tools
+    let symbols_flag = args.iter().position(|a| a == "--symbols");
+    if let Some(i) = symbols_flag {
+        args.remove(i);
+    }
+
+    // keep the source position of every instruction for error messages and the debugger
+    let debug_info_flag = args.iter().position(|a| a == "--debug-info");
+    if let Some(i) = debug_info_flag {
+        args.remove(i);
+    }
 
-    if args.len() != 3 {
-        eprintln!("usage: svm-asm [infile] [outfile]");
+    // assemble for a VM with words of this many bits
+    let mut word_size = "32".to_string();
+    if let Some(i) = args.iter().position(|a| a == "--word-size") {
+        args.remove(i);
+        word_size = if i < args.len() {
+            args.remove(i)
+        } else {
+            String::new()
+        };
+    }
+
+    if args.len() != 3 || !matches!(word_size.as_str(), "32" | "64") {
+        eprintln!(
+            "usage: svm-asm [--symbols] [--debug-info] [--word-size 32|64] [infile] [outfile]"
+        );
         return;
     }
 
@@ -29,15 +57,30 @@ fn main() {
     let tokens = lexer::tokenize(&source);
     dbg!(&tokens);
 
-    let code = codegen::generate(&tokens);
-    let outfile = std::fs::File::create(outfile).unwrap();
-    write_code(&code, outfile).unwrap();
+    let symbols = symbols_flag.is_some();
+    let debug_info = debug_info_flag.is_some();
+    match word_size.as_str() {
+        "64" => assemble::<i64>(&tokens, infile, outfile, symbols, debug_info),
+        _ => assemble::<i32>(&tokens, infile, outfile, symbols, debug_info),
+    }
 }
 
-fn write_code<W: Write>(code: &[i32], mut w: W) -> Result<(), std::io::Error> {
-    for inst in code {
-        let bytes = inst.to_le_bytes();
-        w.write_all(&bytes)?;
+fn assemble<W: Word>(
+    tokens: &[(Token, SourceLocation)],
+    infile: &str,
+    outfile: &str,
+    symbols: bool,
+    debug_info: bool,
+) {
+    let mut program = codegen::generate::<W>(tokens, infile);
+    if !debug_info {
+        program.source_map = None;
+    }
+    let file = std::fs::File::create(outfile).unwrap();
+    program.write(file).unwrap();
+
+    if let (Some(table), true) = (&program.symbols, symbols) {
+        let file = std::fs::File::create(SymbolTable::sidecar_path(outfile)).unwrap();
+        table.write(file).unwrap();
     }
-    Ok(())
 }
@@ -2,9 +2,19 @@
 pub enum Token<'s> {
     LabelDef(&'s str),
     LabelRef(&'s str),
+    StackSize(usize),
+    MemorySize(usize),
+    /// Switches to assembling instructions.
+    Code,
+    /// Switches to assembling initialized memory.
+    Data,
+    /// Reserves zeroed cells in the data segment.
+    Space(usize),
     String(&'s str),
     EscapedString(String),
-    Number(i32),
+    Number(i64),
+    /// A number with a decimal point, kept as written to be parsed at the word size.
+    Float(&'s str),
     Print,
     In,
     Out,
@@ -37,6 +47,22 @@ pub enum Token<'s> {
     Jle,
     Nop,
     Halt,
+    Call,
+    Ret,
+    Sys,
+    Exit,
     Rf,
     Crf,
+    FAdd,
+    FSub,
+    FMul,
+    FDiv,
+    FNeg,
+    FEq,
+    FLt,
+    FLe,
+    IToF,
+    FToI,
+    FIn,
+    FOut,
 }
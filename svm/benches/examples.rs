@@ -0,0 +1,86 @@
+//! Times the interpreter on the example programs.
+//!
+//! The baseline steps through each program one instruction at a time, which executes every
+//! word on its own, as the interpreter did before programs were decoded and literals fused
+//! with the instructions after them.
+//!
+//! Run with `cargo bench -p svm`, adding `--features jit` to also time the JIT.
+
+use std::time::{Duration, Instant};
+
+use svm::io::BufferIo;
+use svm::VM;
+
+const RUNS: usize = 5;
+
+fn main() {
+    let sort_input = sequence(2000);
+    let cases = [
+        ("fib", "25".to_string()),
+        ("wc", text(20000)),
+        ("isort", sort_input.clone()),
+        ("ssort", sort_input),
+    ];
+
+    for (name, input) in cases {
+        let path = format!("{}/../examples/{name}/{name}", env!("CARGO_MANIFEST_DIR"));
+        let bytes = std::fs::read(&path).unwrap_or_else(|e| panic!("unable to read {path}: {e}"));
+
+        let setup = || {
+            let mut vm = VM::with_io(BufferIo::new(&input));
+            vm.load_bytes(&bytes).unwrap();
+            vm
+        };
+        let baseline = time(setup, |vm| while vm.step().unwrap().is_none() {});
+        let best = time(setup, |vm| {
+            vm.run().unwrap();
+        });
+        print!(
+            "{name:<8} {:>10.3} ms  baseline {:>10.3} ms  {:>5.2}x",
+            best.as_secs_f64() * 1000.0,
+            baseline.as_secs_f64() * 1000.0,
+            baseline.as_secs_f64() / best.as_secs_f64()
+        );
+        #[cfg(feature = "jit")]
+        {
+            let best = time(
+                || {
+                    let mut vm = setup();
+                    vm.enable_jit();
+                    vm
+                },
+                |vm| {
+                    vm.run().unwrap();
+                },
+            );
+            print!("  jit {:>10.3} ms", best.as_secs_f64() * 1000.0);
+        }
+        println!();
+    }
+}
+
+/// The best time of running the VMs made by `setup` to the end with `run`.
+fn time(setup: impl Fn() -> VM<BufferIo>, run: impl Fn(&mut VM<BufferIo>)) -> Duration {
+    let mut best = Duration::MAX;
+    for _ in 0..RUNS {
+        let mut vm = setup();
+        let start = Instant::now();
+        run(&mut vm);
+        best = best.min(start.elapsed());
+    }
+    best
+}
+
+/// A shuffled sequence of `n` numbers prefixed with its length.
+fn sequence(n: usize) -> String {
+    let mut input = n.to_string();
+    for i in (0..n).rev() {
+        input.push_str(&format!(" {}", i * 7919 % 10007));
+    }
+    input
+}
+
+/// `lines` lines of words separated by spaces.
+fn text(lines: usize) -> String {
+    "the quick brown fox jumps over the lazy dog\n".repeat(lines)
+}
@@ -0,0 +1,318 @@
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+
+use svm::disasm::{disassemble, instruction_len};
+use svm::io::StdIo;
+use svm::source_map::SourceMap;
+use svm::symbols::SymbolTable;
+use svm::{ExitReason, VMError, Word, VM};
+
+const HELP: &str = "\
+commands:
+  b, break [LOC]          set a breakpoint, or list them
+  d, delete [LOC]         delete a breakpoint, or all of them
+  s, step [N]             execute N instructions (default 1)
+  c, continue             run until a breakpoint or the program stops
+  l, list [LOC] [N]       disassemble N instructions (default 10)
+  stack                   show the stack, top last
+  calls                   show the return addresses of active CALLs
+  x, mem ADDR [N]         show N memory cells (default 1)
+  set mem ADDR VALUE      write a memory cell
+  set stack INDEX VALUE   overwrite a stack value, 0 is the bottom
+  set ip LOC              move execution to LOC
+  i, info                 show the registers and flags
+  h, help                 show this message
+  q, quit                 exit the debugger
+LOC is an address, a label, with or without a leading '@', or FILE:LINE with debug info.
+An empty line repeats the previous command.";
+
+/// Interactive command line debugger driving a [`VM`] one instruction at a time.
+pub struct Debugger<W: Word> {
+    vm: VM<StdIo, W>,
+    symbols: SymbolTable,
+    source_map: Option<SourceMap>,
+    /// The lines of the source file named by the source map, if it could be read.
+    source: Vec<String>,
+    breakpoints: BTreeSet<usize>,
+    stopped: Option<Result<ExitReason, VMError>>,
+}
+
+impl<W: Word> Debugger<W> {
+    pub fn new(vm: VM<StdIo, W>, symbols: SymbolTable, source_map: Option<SourceMap>) -> Self {
+        let source = source_map
+            .as_ref()
+            .and_then(|map| std::fs::read_to_string(map.file()).ok())
+            .map_or(Vec::new(), |text| {
+                text.lines().map(str::to_string).collect()
+            });
+        Debugger {
+            vm,
+            symbols,
+            source_map,
+            source,
+            breakpoints: BTreeSet::new(),
+            stopped: None,
+        }
+    }
+
+    pub fn run(&mut self) -> io::Result<()> {
+        println!("svm debugger, type 'help' for a list of commands");
+        self.show_current();
+
+        let stdin = io::stdin();
+        let mut previous = String::new();
+        loop {
+            print!("(svm) ");
+            io::stdout().flush()?;
+
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            let line = match line.trim() {
+                "" => previous.clone(),
+                line => line.to_string(),
+            };
+            match self.command(&line) {
+                Ok(true) => {}
+                Ok(false) => return Ok(()),
+                Err(e) => println!("{e}"),
+            }
+            previous = line;
+        }
+    }
+
+    /// Executes a debugger command, returning `false` if the debugger should exit.
+    fn command(&mut self, line: &str) -> Result<bool, String> {
+        let args: Vec<&str> = line.split_whitespace().collect();
+        let Some((&cmd, args)) = args.split_first() else {
+            return Ok(true);
+        };
+        match (cmd, args) {
+            ("b" | "break", []) => {
+                for &addr in &self.breakpoints {
+                    println!("  {}", self.location(addr));
+                }
+            }
+            ("b" | "break", [loc]) => {
+                let addr = self.parse_location(loc)?;
+                self.breakpoints.insert(addr);
+                println!("breakpoint at {}", self.location(addr));
+            }
+            ("d" | "delete", []) => self.breakpoints.clear(),
+            ("d" | "delete", [loc]) => {
+                let addr = self.parse_location(loc)?;
+                if !self.breakpoints.remove(&addr) {
+                    return Err(format!("no breakpoint at {}", self.location(addr)));
+                }
+            }
+            ("s" | "step", []) => self.step(1),
+            ("s" | "step", [n]) => self.step(parse_number(n)?),
+            ("c" | "continue", []) => self.resume(),
+            ("l" | "list", []) => self.list(self.vm.ip().saturating_sub(2), 10),
+            ("l" | "list", [loc]) => self.list(self.parse_location(loc)?, 10),
+            ("l" | "list", [loc, n]) => self.list(self.parse_location(loc)?, parse_number(n)?),
+            ("stack", []) => println!("{:?}", self.vm.stack()),
+            ("calls", []) => {
+                for &ret in self.vm.call_stack().iter().rev() {
+                    println!("  returns to {}", self.location(ret));
+                }
+            }
+            ("x" | "mem", [addr]) => self.show_memory(parse_number(addr)?, 1)?,
+            ("x" | "mem", [addr, n]) => self.show_memory(parse_number(addr)?, parse_number(n)?)?,
+            ("set", ["mem", addr, value]) => {
+                let addr: usize = parse_number(addr)?;
+                let cell = self
+                    .vm
+                    .memory_mut()
+                    .get_mut(addr)
+                    .ok_or(format!("invalid memory address {addr}"))?;
+                *cell = parse_number(value)?;
+            }
+            ("set", ["stack", index, value]) => {
+                let index: usize = parse_number(index)?;
+                let cell = self
+                    .vm
+                    .stack_mut()
+                    .get_mut(index)
+                    .ok_or(format!("invalid stack index {index}"))?;
+                *cell = parse_number(value)?;
+            }
+            ("set", ["ip", loc]) => {
+                let addr = self.parse_location(loc)?;
+                self.vm.set_ip(addr);
+                self.stopped = None;
+                self.show_current();
+            }
+            ("i" | "info", []) => self.show_info(),
+            ("h" | "help", []) => println!("{HELP}"),
+            ("q" | "quit", []) => return Ok(false),
+            _ => return Err(format!("invalid command '{line}', type 'help' for help")),
+        }
+        Ok(true)
+    }
+
+    fn step(&mut self, n: usize) {
+        for _ in 0..n {
+            if !self.tick() {
+                return;
+            }
+        }
+        self.show_current();
+    }
+
+    fn resume(&mut self) {
+        // always move off the breakpoint we may be stopped at
+        if !self.tick() {
+            return;
+        }
+        while !self.breakpoints.contains(&self.vm.ip()) {
+            if !self.tick() {
+                return;
+            }
+        }
+        println!("breakpoint at {}", self.location(self.vm.ip()));
+        self.show_current();
+    }
+
+    /// Executes one instruction, returning `false` and reporting why if the program stopped.
+    fn tick(&mut self) -> bool {
+        if let Some(stopped) = &self.stopped {
+            self.report(stopped);
+            return false;
+        }
+        let stopped = match self.vm.step() {
+            Ok(None) => return true,
+            Ok(Some(reason)) => Ok(reason),
+            Err(e) => Err(e),
+        };
+        io::stdout().flush().ok();
+        self.report(&stopped);
+        // a yielded program can run again once it is given more fuel
+        if stopped != Ok(ExitReason::Yielded) {
+            self.stopped = Some(stopped);
+        }
+        false
+    }
+
+    fn report(&self, stopped: &Result<ExitReason, VMError>) {
+        match stopped {
+            Ok(ExitReason::Halted) => println!("program halted"),
+            Ok(ExitReason::Exited(status)) => println!("program exited with status {status}"),
+            Ok(ExitReason::EndOfProgram) => println!("program reached its end"),
+            Ok(ExitReason::Yielded) => println!("step limit exceeded"),
+            Err(e) => {
+                println!("program failed at {}: {}", self.location(e.ip), e.kind);
+                self.show_source(e.ip);
+            }
+        }
+    }
+
+    fn list(&self, start: usize, n: usize) {
+        let program = self.vm.program();
+        let mut addr = start;
+        for _ in 0..n {
+            if addr >= program.len() {
+                break;
+            }
+            for name in self.symbols.labels_at(addr) {
+                println!("       :{name}");
+            }
+            let marker = if addr == self.vm.ip() { "=>" } else { "  " };
+            let bp = if self.breakpoints.contains(&addr) {
+                '*'
+            } else {
+                ' '
+            };
+            let inst = disassemble(program, addr, Some(&self.symbols));
+            println!("{marker}{bp}{addr:>5}  {inst}");
+            addr += instruction_len(program, addr);
+        }
+    }
+
+    fn show_current(&self) {
+        let ip = self.vm.ip();
+        if ip < self.vm.program().len() {
+            let inst = disassemble(self.vm.program(), ip, Some(&self.symbols));
+            println!("{}: {inst}", self.location(ip));
+            self.show_source(ip);
+        } else {
+            println!("{}: end of program", self.location(ip));
+        }
+    }
+
+    /// Prints the source line the instruction at `addr` was assembled from.
+    fn show_source(&self, addr: usize) {
+        let Some(location) = self.source_map.as_ref().and_then(|map| map.get(addr)) else {
+            return;
+        };
+        let text = (location.line.checked_sub(1)).and_then(|i| self.source.get(i));
+        if let Some(text) = text {
+            println!("{:>7} | {text}", location.line);
+        }
+    }
+
+    fn show_memory(&self, addr: usize, n: usize) -> Result<(), String> {
+        let end = addr.saturating_add(n);
+        let cells = self
+            .vm
+            .memory()
+            .get(addr..end)
+            .ok_or(format!("invalid memory range {addr}..{end}"))?;
+        for (i, v) in cells.iter().enumerate() {
+            println!("  [{}] {v}", addr + i);
+        }
+        Ok(())
+    }
+
+    fn show_info(&self) {
+        println!("ip  {}", self.location(self.vm.ip()));
+        println!("sp  {}", self.vm.sp());
+        println!("hf  {}", self.vm.hf());
+        println!("rf  {}", self.vm.rf());
+        if let Some(fuel) = self.vm.fuel() {
+            println!("fuel {fuel}");
+        }
+    }
+
+    fn location(&self, addr: usize) -> String {
+        let location = self.symbols.location(addr);
+        match self.source_map.as_ref().and_then(|map| map.describe(addr)) {
+            Some(source) => format!("{location} ({source})"),
+            None => location,
+        }
+    }
+
+    fn parse_location(&self, loc: &str) -> Result<usize, String> {
+        let addr = match loc.parse() {
+            Ok(addr) => addr,
+            Err(_) if loc.contains(':') => self.parse_source_line(loc)?,
+            Err(_) => {
+                let name = loc.strip_prefix('@').unwrap_or(loc);
+                self.symbols
+                    .address(name)
+                    .ok_or(format!("unknown label '{name}'"))?
+            }
+        };
+        if addr >= self.vm.program().len() {
+            return Err(format!("address {addr} is outside the program"));
+        }
+        Ok(addr)
+    }
+
+    /// Resolves `FILE:LINE` to the first address assembled from that line.
+    fn parse_source_line(&self, loc: &str) -> Result<usize, String> {
+        let map = (self.source_map.as_ref()).ok_or("the program has no debug info")?;
+        let (file, line) = loc.rsplit_once(':').unwrap();
+        let line = parse_number(line)?;
+        if !std::path::Path::new(map.file()).ends_with(file) {
+            return Err(format!("unknown source file '{file}'"));
+        }
+        map.address_of_line(line)
+            .ok_or(format!("no code at {file}:{line}"))
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(s: &str) -> Result<T, String> {
+    s.parse().map_err(|_| format!("invalid number '{s}'"))
+}
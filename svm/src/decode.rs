@@ -0,0 +1,210 @@
+use crate::instructions::*;
+use crate::word::Word;
+
+/// The comparison made by a conditional jump between the top of the stack and the value below it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Cond {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+impl Cond {
+    fn of(inst: i32) -> Option<Cond> {
+        match inst {
+            JE => Some(Cond::Eq),
+            JNE => Some(Cond::Ne),
+            JG => Some(Cond::Gt),
+            JGE => Some(Cond::Ge),
+            JL => Some(Cond::Lt),
+            JLE => Some(Cond::Le),
+            _ => None,
+        }
+    }
+
+    /// Whether the jump is taken, `a` being the top of the stack and `b` the value below it.
+    #[inline(always)]
+    pub(crate) fn test<W: Word>(self, a: W, b: W) -> bool {
+        match self {
+            Cond::Eq => a == b,
+            Cond::Ne => a != b,
+            Cond::Gt => a > b,
+            Cond::Ge => a >= b,
+            Cond::Lt => a < b,
+            Cond::Le => a <= b,
+        }
+    }
+}
+
+/// An instruction decoded once at load time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Op<W> {
+    Push(W),
+    In,
+    Out,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Neg,
+    Inc,
+    Dec,
+    And,
+    Or,
+    Not,
+    Xor,
+    Shl,
+    Shr,
+    Pop,
+    Dup,
+    Swp,
+    Ovr,
+    Load,
+    Stor,
+    Jmp,
+    Jump(Cond),
+    Call,
+    Ret,
+    Sys,
+    Exit,
+    Rf,
+    Crf,
+    Nop,
+    Halt,
+    FAdd,
+    FSub,
+    FMul,
+    FDiv,
+    FNeg,
+    FEq,
+    FLt,
+    FLe,
+    IToF,
+    FToI,
+    FIn,
+    FOut,
+    /// `PUSH` with the operand word after it, which it covers like fused ops but counts as a
+    /// single instruction.
+    PushNext(W),
+    Unknown(i64),
+
+    // A literal fused with the instruction after it, which takes the literal off the stack.
+    // These cover two words and behave exactly like executing both.
+    /// A literal address followed by `JMP`, already checked to be inside the program.
+    JmpTo(usize),
+    /// A literal address followed by a conditional jump, already checked.
+    JumpTo(Cond, usize),
+    /// A literal address followed by `CALL`, already checked.
+    CallTo(usize),
+    /// A literal address followed by `LOAD`.
+    LoadFrom(W),
+    /// A literal address followed by `STOR`.
+    StorTo(W),
+}
+
+impl<W: Word> Op<W> {
+    /// Decodes a single word without fusing.
+    ///
+    /// `PUSH` needs the word after it, so on its own, as at the end of a program, it is an
+    /// unknown instruction.
+    pub(crate) fn decode(word: W) -> Op<W> {
+        if word.is_literal() {
+            return Op::Push(word);
+        }
+        let Some(inst) = word.opcode() else {
+            return Op::Unknown(word.into());
+        };
+        if let Some(cond) = Cond::of(inst) {
+            return Op::Jump(cond);
+        }
+        match inst {
+            IN => Op::In,
+            OUT => Op::Out,
+            ADD => Op::Add,
+            SUB => Op::Sub,
+            MUL => Op::Mul,
+            DIV => Op::Div,
+            MOD => Op::Mod,
+            NEG => Op::Neg,
+            INC => Op::Inc,
+            DEC => Op::Dec,
+            AND => Op::And,
+            OR => Op::Or,
+            NOT => Op::Not,
+            XOR => Op::Xor,
+            SHL => Op::Shl,
+            SHR => Op::Shr,
+            POP => Op::Pop,
+            DUP => Op::Dup,
+            SWP => Op::Swp,
+            OVR => Op::Ovr,
+            LOAD => Op::Load,
+            STOR => Op::Stor,
+            JMP => Op::Jmp,
+            CALL => Op::Call,
+            RET => Op::Ret,
+            SYS => Op::Sys,
+            EXIT => Op::Exit,
+            RF => Op::Rf,
+            CRF => Op::Crf,
+            NOP => Op::Nop,
+            HALT => Op::Halt,
+            FADD => Op::FAdd,
+            FSUB => Op::FSub,
+            FMUL => Op::FMul,
+            FDIV => Op::FDiv,
+            FNEG => Op::FNeg,
+            FEQ => Op::FEq,
+            FLT => Op::FLt,
+            FLE => Op::FLe,
+            ITOF => Op::IToF,
+            FTOI => Op::FToI,
+            FIN => Op::FIn,
+            FOUT => Op::FOut,
+            unk => Op::Unknown(unk.into()),
+        }
+    }
+
+    /// Whether the op stands for a literal and the instruction after it.
+    #[inline(always)]
+    pub(crate) fn is_fused(self) -> bool {
+        matches!(
+            self,
+            Op::JmpTo(_) | Op::JumpTo(..) | Op::CallTo(_) | Op::LoadFrom(_) | Op::StorTo(_)
+        )
+    }
+}
+
+/// Decodes `program` into one op per word, so addresses are unchanged.
+///
+/// `PUSH` takes the word after it as its operand. A literal followed by an instruction that
+/// consumes it is fused into a single op at the literal's address. In both cases the second
+/// word keeps its own op, so jumping to it still works.
+pub(crate) fn decode<W: Word>(program: &[W]) -> Vec<Op<W>> {
+    let mut code: Vec<Op<W>> = program.iter().map(|&inst| Op::decode(inst)).collect();
+    for (op, pair) in code.iter_mut().zip(program.windows(2)) {
+        let (lit, next) = (pair[0], pair[1]);
+        if lit.opcode() == Some(PUSH) {
+            *op = Op::PushNext(next);
+            continue;
+        }
+        let Some(next) = next.opcode().filter(|_| lit.is_literal()) else {
+            continue;
+        };
+        let addr = Into::<i64>::into(lit) as usize;
+        let valid_target = addr < program.len();
+        *op = match (next, Cond::of(next)) {
+            (JMP, _) if valid_target => Op::JmpTo(addr),
+            (CALL, _) if valid_target => Op::CallTo(addr),
+            (LOAD, _) => Op::LoadFrom(lit),
+            (STOR, _) => Op::StorTo(lit),
+            (_, Some(cond)) if valid_target => Op::JumpTo(cond, addr),
+            _ => continue,
+        };
+    }
+    code
+}
@@ -0,0 +1,204 @@
+use std::io::{self, Read, Write};
+use std::time::Instant;
+
+/// A Rust object mapped into a range of data memory addresses.
+///
+/// `LOAD` and `STOR` on a mapped address call [`read`](Device::read) and
+/// [`write`](Device::write) with the offset of the address from the start of the range instead
+/// of touching memory. An error stops the program with [`ErrorKind::IOError`](crate::ErrorKind).
+/// Values read are truncated to the word size of the VM.
+pub trait Device {
+    /// The number of addresses the device occupies.
+    fn cells(&self) -> usize;
+
+    fn read(&mut self, offset: usize) -> io::Result<i64>;
+
+    fn write(&mut self, offset: usize, v: i64) -> io::Result<()>;
+}
+
+impl<T: Device + ?Sized> Device for Box<T> {
+    fn cells(&self) -> usize {
+        (**self).cells()
+    }
+
+    fn read(&mut self, offset: usize) -> io::Result<i64> {
+        (**self).read(offset)
+    }
+
+    fn write(&mut self, offset: usize, v: i64) -> io::Result<()> {
+        (**self).write(offset, v)
+    }
+}
+
+/// Returned when mapping a device over addresses another device is mapped at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OverlapError {
+    /// The first address of the device that could not be mapped.
+    pub start: usize,
+}
+
+impl std::fmt::Display for OverlapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "device at {} overlaps another device", self.start)
+    }
+}
+
+impl std::error::Error for OverlapError {}
+
+/// Routes accesses to mapped addresses to their devices.
+#[derive(Default)]
+pub(crate) struct Bus {
+    /// Mapped devices ordered by their first address.
+    devices: Vec<(usize, Box<dyn Device>)>,
+}
+
+impl Bus {
+    pub fn map(&mut self, start: usize, device: Box<dyn Device>) -> Result<(), OverlapError> {
+        let end = start.saturating_add(device.cells());
+        let i = self.devices.partition_point(|(s, _)| *s < start);
+        let overlaps_prev = i > 0 && {
+            let (s, d) = &self.devices[i - 1];
+            s.saturating_add(d.cells()) > start
+        };
+        let overlaps_next = self.devices.get(i).is_some_and(|(s, _)| *s < end);
+        if overlaps_prev || overlaps_next {
+            return Err(OverlapError { start });
+        }
+        self.devices.insert(i, (start, device));
+        Ok(())
+    }
+
+    /// The lowest mapped address, or `usize::MAX` if nothing is mapped.
+    pub fn first_address(&self) -> usize {
+        self.devices.first().map_or(usize::MAX, |(s, _)| *s)
+    }
+
+    /// The device mapped at `addr` and the offset of `addr` into it.
+    pub fn device_at(&mut self, addr: usize) -> Option<(&mut dyn Device, usize)> {
+        let i = self
+            .devices
+            .partition_point(|(s, _)| *s <= addr)
+            .checked_sub(1)?;
+        let (start, device) = &mut self.devices[i];
+        let offset = addr - *start;
+        (offset < device.cells()).then_some((device.as_mut(), offset))
+    }
+}
+
+/// Counts milliseconds. Reading gives the time since the timer was created or last written to,
+/// wrapping around.
+pub struct Timer {
+    start: Instant,
+}
+
+impl Timer {
+    pub fn new() -> Self {
+        Timer {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Default for Timer {
+    fn default() -> Self {
+        Timer::new()
+    }
+}
+
+impl Device for Timer {
+    fn cells(&self) -> usize {
+        1
+    }
+
+    fn read(&mut self, _offset: usize) -> io::Result<i64> {
+        Ok(self.start.elapsed().as_millis() as i64)
+    }
+
+    fn write(&mut self, _offset: usize, _v: i64) -> io::Result<()> {
+        self.start = Instant::now();
+        Ok(())
+    }
+}
+
+/// Character I/O on the process's stdin and stdout, independent of the raw mode flag.
+///
+/// Offset 0 reads a byte of input, or [`EOF`](crate::instructions::EOF) at its end, and writes
+/// a character. Offset 1 writes a number followed by a newline.
+#[derive(Default)]
+pub struct Console;
+
+impl Console {
+    pub fn new() -> Self {
+        Console
+    }
+}
+
+impl Device for Console {
+    fn cells(&self) -> usize {
+        2
+    }
+
+    fn read(&mut self, offset: usize) -> io::Result<i64> {
+        if offset != 0 {
+            return Ok(0);
+        }
+        io::stdout().flush()?;
+        let mut byte = [0];
+        match io::stdin().read(&mut byte)? {
+            0 => Ok(crate::instructions::EOF.into()),
+            _ => Ok(byte[0] as i64),
+        }
+    }
+
+    fn write(&mut self, offset: usize, v: i64) -> io::Result<()> {
+        let mut out = io::stdout();
+        match offset {
+            0 => write!(
+                out,
+                "{}",
+                u32::try_from(v)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .unwrap_or(char::REPLACEMENT_CHARACTER)
+            ),
+            _ => writeln!(out, "{v}"),
+        }
+    }
+}
+
+/// A pseudo-random number generator. Reading gives the next non-negative number, writing
+/// reseeds it.
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        let mut random = Random { state: 0 };
+        random.seed(seed);
+        random
+    }
+
+    fn seed(&mut self, seed: u64) {
+        // xorshift gets stuck at zero
+        self.state = (seed ^ 0x9E37_79B9_7F4A_7C15).max(1);
+    }
+}
+
+impl Device for Random {
+    fn cells(&self) -> usize {
+        1
+    }
+
+    fn read(&mut self, _offset: usize) -> io::Result<i64> {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        Ok((self.state >> 33) as i64)
+    }
+
+    fn write(&mut self, _offset: usize, v: i64) -> io::Result<()> {
+        self.seed(v as u64);
+        Ok(())
+    }
+}
@@ -0,0 +1,47 @@
+use crate::instructions;
+use crate::symbols::SymbolTable;
+use crate::word::Word;
+
+/// Formats the instruction at `addr` in assembler syntax.
+///
+/// A literal that feeds an instruction taking an address is shown as a label reference if
+/// `symbols` has a label for it.
+pub fn disassemble<W: Word>(program: &[W], addr: usize, symbols: Option<&SymbolTable>) -> String {
+    let inst = program[addr];
+    if let Some(operand) = push_operand(program, addr) {
+        return format!("PUSH {operand}");
+    }
+    if !inst.is_literal() {
+        return (inst.opcode())
+            .and_then(instructions::mnemonic)
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("?? {inst}"));
+    }
+    let feeds_address = program
+        .get(addr + 1)
+        .and_then(|next| next.opcode())
+        .is_some_and(instructions::takes_address);
+    if feeds_address {
+        let target = Into::<i64>::into(inst) as usize;
+        if let Some(name) = symbols.and_then(|s| s.labels_at(target).first()) {
+            return format!("@{name}");
+        }
+    }
+    inst.to_string()
+}
+
+/// The number of words the instruction at `addr` covers, 2 for `PUSH` and its operand.
+pub fn instruction_len<W: Word>(program: &[W], addr: usize) -> usize {
+    if push_operand(program, addr).is_some() {
+        2
+    } else {
+        1
+    }
+}
+
+fn push_operand<W: Word>(program: &[W], addr: usize) -> Option<W> {
+    if program[addr].opcode() != Some(instructions::PUSH) {
+        return None;
+    }
+    program.get(addr + 1).copied()
+}
@@ -42,6 +42,113 @@ pub const JLE: i32 = -29;
 pub const NOP: i32 = -30;
 pub const HALT: i32 = -31;
 
+// Subroutines
+pub const CALL: i32 = -32;
+pub const RET: i32 = -33;
+
+// Host
+pub const SYS: i32 = -34;
+
+// Termination
+/// Pops a status and stops the program with it.
+pub const EXIT: i32 = -35;
+
+// Floating point, on words reinterpreted as floats of the same size
+pub const FADD: i32 = -36;
+pub const FSUB: i32 = -37;
+pub const FMUL: i32 = -38;
+pub const FDIV: i32 = -39;
+pub const FNEG: i32 = -40;
+/// Pops `a`, then `b`, and pushes 1 if `b == a`, otherwise 0.
+pub const FEQ: i32 = -41;
+/// Pops `a`, then `b`, and pushes 1 if `b < a`, otherwise 0.
+pub const FLT: i32 = -42;
+/// Pops `a`, then `b`, and pushes 1 if `b <= a`, otherwise 0.
+pub const FLE: i32 = -43;
+/// Converts an integer to the nearest float.
+pub const ITOF: i32 = -44;
+/// Converts a float to an integer, rounding toward zero and saturating, with NaN becoming 0.
+pub const FTOI: i32 = -45;
+/// Reads a float, like `IN` reads an integer.
+pub const FIN: i32 = -46;
+/// Writes a float, like `OUT` writes an integer.
+pub const FOUT: i32 = -47;
+
+// Literals
+/// Pushes the word after it and skips over it. Negative values, which would be taken for
+/// instructions on their own, can only be pushed this way.
+pub const PUSH: i32 = -48;
+
 // Flags
 pub const RF: i32 = -101;
 pub const CRF: i32 = -102;
+
+// Values
+/// Pushed by `IN` in raw mode once the input is exhausted.
+pub const EOF: i32 = -1;
+
+/// Returns the assembler mnemonic of an opcode, or `None` for literals and unknown opcodes.
+pub fn mnemonic(inst: i32) -> Option<&'static str> {
+    let name = match inst {
+        IN => "IN",
+        OUT => "OUT",
+        ADD => "ADD",
+        SUB => "SUB",
+        MUL => "MUL",
+        DIV => "DIV",
+        MOD => "MOD",
+        NEG => "NEG",
+        INC => "INC",
+        DEC => "DEC",
+        AND => "AND",
+        OR => "OR",
+        NOT => "NOT",
+        XOR => "XOR",
+        SHL => "SHL",
+        SHR => "SHR",
+        POP => "POP",
+        DUP => "DUP",
+        SWP => "SWP",
+        OVR => "OVR",
+        LOAD => "LOAD",
+        STOR => "STOR",
+        JMP => "JMP",
+        JE => "JE",
+        JNE => "JNE",
+        JG => "JG",
+        JGE => "JGE",
+        JL => "JL",
+        JLE => "JLE",
+        NOP => "NOP",
+        HALT => "HALT",
+        CALL => "CALL",
+        RET => "RET",
+        SYS => "SYS",
+        EXIT => "EXIT",
+        FADD => "FADD",
+        FSUB => "FSUB",
+        FMUL => "FMUL",
+        FDIV => "FDIV",
+        FNEG => "FNEG",
+        FEQ => "FEQ",
+        FLT => "FLT",
+        FLE => "FLE",
+        ITOF => "ITOF",
+        FTOI => "FTOI",
+        FIN => "FIN",
+        FOUT => "FOUT",
+        PUSH => "PUSH",
+        RF => "RF",
+        CRF => "CRF",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Whether an opcode takes an address from the top of the stack.
+pub fn takes_address(inst: i32) -> bool {
+    matches!(
+        inst,
+        LOAD | STOR | JMP | JE | JNE | JG | JGE | JL | JLE | CALL
+    )
+}
@@ -0,0 +1,353 @@
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+
+/// The host side of the `IN` and `OUT` instructions.
+///
+/// Numbers are passed as `i64` whatever the word size of the VM. `IN` fails with an I/O error
+/// if the number read does not fit in a word. Floats are passed as text, which the VM parses
+/// and formats with the precision of its words.
+pub trait Io {
+    /// Reads a number for `IN`.
+    fn read_number(&mut self) -> io::Result<i64>;
+
+    /// Reads the text of a number for `FIN`.
+    fn read_float(&mut self) -> io::Result<String>;
+
+    /// Reads a single character for `IN` in raw mode, or `None` at the end of input.
+    fn read_char(&mut self) -> io::Result<Option<char>>;
+
+    /// Writes a number for `OUT`.
+    fn write_number(&mut self, v: i64) -> io::Result<()>;
+
+    /// Writes a formatted number for `FOUT`.
+    fn write_float(&mut self, v: &str) -> io::Result<()>;
+
+    /// Writes a single character for `OUT` in raw mode.
+    fn write_char(&mut self, c: char) -> io::Result<()>;
+
+    fn flush(&mut self) -> io::Result<()>;
+}
+
+impl<T: Io + ?Sized> Io for Box<T> {
+    fn read_number(&mut self) -> io::Result<i64> {
+        (**self).read_number()
+    }
+
+    fn read_float(&mut self) -> io::Result<String> {
+        (**self).read_float()
+    }
+
+    fn read_char(&mut self) -> io::Result<Option<char>> {
+        (**self).read_char()
+    }
+
+    fn write_number(&mut self, v: i64) -> io::Result<()> {
+        (**self).write_number(v)
+    }
+
+    fn write_float(&mut self, v: &str) -> io::Result<()> {
+        (**self).write_float(v)
+    }
+
+    fn write_char(&mut self, c: char) -> io::Result<()> {
+        (**self).write_char(c)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (**self).flush()
+    }
+}
+
+impl<T: Io + ?Sized> Io for &mut T {
+    fn read_number(&mut self) -> io::Result<i64> {
+        (**self).read_number()
+    }
+
+    fn read_float(&mut self) -> io::Result<String> {
+        (**self).read_float()
+    }
+
+    fn read_char(&mut self) -> io::Result<Option<char>> {
+        (**self).read_char()
+    }
+
+    fn write_number(&mut self, v: i64) -> io::Result<()> {
+        (**self).write_number(v)
+    }
+
+    fn write_float(&mut self, v: &str) -> io::Result<()> {
+        (**self).write_float(v)
+    }
+
+    fn write_char(&mut self, c: char) -> io::Result<()> {
+        (**self).write_char(c)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (**self).flush()
+    }
+}
+
+/// Reads from the process's stdin and writes to its stdout.
+///
+/// Numbers are read one per line after a `?` prompt.
+#[derive(Default)]
+pub struct StdIo {
+    pending: VecDeque<char>,
+}
+
+impl StdIo {
+    pub fn new() -> Self {
+        StdIo::default()
+    }
+
+    fn read_line(&mut self) -> io::Result<bool> {
+        let mut line = String::new();
+        let read = io::stdin().lock().read_line(&mut line)?;
+        self.pending.extend(line.chars());
+        Ok(read != 0)
+    }
+
+    /// Reads the rest of a line after a `?` prompt if there is nothing left.
+    fn read_text(&mut self) -> io::Result<String> {
+        if self.pending.is_empty() {
+            print!("?");
+            io::stdout().flush()?;
+            if !self.read_line()? {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+        let line: String = self.pending.drain(..).collect();
+        Ok(line.trim().to_string())
+    }
+}
+
+impl Io for StdIo {
+    fn read_number(&mut self) -> io::Result<i64> {
+        self.read_text()?
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn read_float(&mut self) -> io::Result<String> {
+        self.read_text()
+    }
+
+    fn read_char(&mut self) -> io::Result<Option<char>> {
+        if self.pending.is_empty() {
+            io::stdout().flush()?;
+            self.read_line()?;
+        }
+        Ok(self.pending.pop_front())
+    }
+
+    fn write_number(&mut self, v: i64) -> io::Result<()> {
+        writeln!(io::stdout(), "{v}")
+    }
+
+    fn write_float(&mut self, v: &str) -> io::Result<()> {
+        writeln!(io::stdout(), "{v}")
+    }
+
+    fn write_char(&mut self, c: char) -> io::Result<()> {
+        write!(io::stdout(), "{c}")
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()
+    }
+}
+
+/// Reads from an in-memory string and collects output into another.
+///
+/// Numbers are read as whitespace separated tokens and written one per line.
+#[derive(Default)]
+pub struct BufferIo {
+    input: VecDeque<char>,
+    output: String,
+}
+
+impl BufferIo {
+    pub fn new(input: &str) -> Self {
+        BufferIo {
+            input: input.chars().collect(),
+            output: String::new(),
+        }
+    }
+
+    /// Appends more text to the remaining input.
+    pub fn push_input(&mut self, input: &str) {
+        self.input.extend(input.chars());
+    }
+
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+
+    /// Returns the output written so far and clears it.
+    pub fn take_output(&mut self) -> String {
+        std::mem::take(&mut self.output)
+    }
+
+    /// Reads the next whitespace separated token.
+    fn read_token(&mut self) -> io::Result<String> {
+        while self.input.front().is_some_and(|c| c.is_whitespace()) {
+            self.input.pop_front();
+        }
+        let mut token = String::new();
+        while let Some(c) = self.input.front().copied() {
+            if c.is_whitespace() {
+                break;
+            }
+            token.push(c);
+            self.input.pop_front();
+        }
+        if token.is_empty() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(token)
+    }
+}
+
+impl Io for BufferIo {
+    fn read_number(&mut self) -> io::Result<i64> {
+        self.read_token()?
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn read_float(&mut self) -> io::Result<String> {
+        self.read_token()
+    }
+
+    fn read_char(&mut self) -> io::Result<Option<char>> {
+        Ok(self.input.pop_front())
+    }
+
+    fn write_number(&mut self, v: i64) -> io::Result<()> {
+        self.output.push_str(&v.to_string());
+        self.output.push('\n');
+        Ok(())
+    }
+
+    fn write_float(&mut self, v: &str) -> io::Result<()> {
+        self.output.push_str(v);
+        self.output.push('\n');
+        Ok(())
+    }
+
+    fn write_char(&mut self, c: char) -> io::Result<()> {
+        self.output.push(c);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+type ReadNumberFn = Box<dyn FnMut() -> io::Result<i64>>;
+type ReadFloatFn = Box<dyn FnMut() -> io::Result<String>>;
+type ReadCharFn = Box<dyn FnMut() -> io::Result<Option<char>>>;
+type WriteNumberFn = Box<dyn FnMut(i64) -> io::Result<()>>;
+type WriteFloatFn = Box<dyn FnMut(&str) -> io::Result<()>>;
+type WriteCharFn = Box<dyn FnMut(char) -> io::Result<()>>;
+type FlushFn = Box<dyn FnMut() -> io::Result<()>>;
+
+/// Forwards every request to user supplied closures.
+///
+/// Without a closure, reads behave as if the input is exhausted and writes are discarded.
+pub struct CallbackIo {
+    read_number: ReadNumberFn,
+    read_float: ReadFloatFn,
+    read_char: ReadCharFn,
+    write_number: WriteNumberFn,
+    write_float: WriteFloatFn,
+    write_char: WriteCharFn,
+    flush: FlushFn,
+}
+
+impl CallbackIo {
+    pub fn new() -> Self {
+        CallbackIo {
+            read_number: Box::new(|| Err(io::ErrorKind::UnexpectedEof.into())),
+            read_float: Box::new(|| Err(io::ErrorKind::UnexpectedEof.into())),
+            read_char: Box::new(|| Ok(None)),
+            write_number: Box::new(|_| Ok(())),
+            write_float: Box::new(|_| Ok(())),
+            write_char: Box::new(|_| Ok(())),
+            flush: Box::new(|| Ok(())),
+        }
+    }
+
+    pub fn on_read_number(mut self, f: impl FnMut() -> io::Result<i64> + 'static) -> Self {
+        self.read_number = Box::new(f);
+        self
+    }
+
+    pub fn on_read_float(mut self, f: impl FnMut() -> io::Result<String> + 'static) -> Self {
+        self.read_float = Box::new(f);
+        self
+    }
+
+    pub fn on_read_char(mut self, f: impl FnMut() -> io::Result<Option<char>> + 'static) -> Self {
+        self.read_char = Box::new(f);
+        self
+    }
+
+    pub fn on_write_number(mut self, f: impl FnMut(i64) -> io::Result<()> + 'static) -> Self {
+        self.write_number = Box::new(f);
+        self
+    }
+
+    pub fn on_write_float(mut self, f: impl FnMut(&str) -> io::Result<()> + 'static) -> Self {
+        self.write_float = Box::new(f);
+        self
+    }
+
+    pub fn on_write_char(mut self, f: impl FnMut(char) -> io::Result<()> + 'static) -> Self {
+        self.write_char = Box::new(f);
+        self
+    }
+
+    pub fn on_flush(mut self, f: impl FnMut() -> io::Result<()> + 'static) -> Self {
+        self.flush = Box::new(f);
+        self
+    }
+}
+
+impl Default for CallbackIo {
+    fn default() -> Self {
+        CallbackIo::new()
+    }
+}
+
+impl Io for CallbackIo {
+    fn read_number(&mut self) -> io::Result<i64> {
+        (self.read_number)()
+    }
+
+    fn read_float(&mut self) -> io::Result<String> {
+        (self.read_float)()
+    }
+
+    fn read_char(&mut self) -> io::Result<Option<char>> {
+        (self.read_char)()
+    }
+
+    fn write_number(&mut self, v: i64) -> io::Result<()> {
+        (self.write_number)(v)
+    }
+
+    fn write_float(&mut self, v: &str) -> io::Result<()> {
+        (self.write_float)(v)
+    }
+
+    fn write_char(&mut self, c: char) -> io::Result<()> {
+        (self.write_char)(c)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (self.flush)()
+    }
+}
@@ -0,0 +1,537 @@
+//! Compiles hot straight-line regions of pre-decoded code to x86-64 machine code.
+//!
+//! A block starts at an address the interpreter has reached often enough and runs until a
+//! jump with a literal target, or until an instruction the compiler does not handle. Before
+//! every instruction the generated code checks everything that could make it fail: the stack
+//! depth, free stack space, memory addresses and divisors. If a check fails the block returns
+//! to the interpreter at that instruction without having executed it, so the interpreter
+//! raises the error or grows the stack exactly as it would have without the JIT. Dynamic
+//! jumps, calls, I/O, flags and `HALT` always end a block and are left to the interpreter.
+//! Only code with 32-bit words is compiled.
+
+#[cfg(not(all(target_arch = "x86_64", target_os = "linux")))]
+compile_error!("the `jit` feature is only supported on x86-64 Linux");
+
+use crate::decode::{Cond, Op};
+
+/// The number of times an address must be reached before a block is compiled there.
+const HOT_THRESHOLD: u32 = 64;
+/// The number of words a block may span.
+const MAX_BLOCK_WORDS: usize = 1024;
+
+/// The VM state a block reads and updates. Offsets are hard-coded in the generated code.
+#[repr(C)]
+pub(crate) struct Context {
+    pub stack: *mut i32,
+    pub sp: u64,
+    pub stack_len: u64,
+    pub memory: *mut i32,
+    pub memory_len: u64,
+    /// Set by the block to the address to continue at.
+    pub ip: u64,
+    /// Set by the block to the number of instructions it executed.
+    pub executed: u64,
+}
+
+const CTX_STACK: i32 = 0;
+const CTX_SP: i32 = 8;
+const CTX_STACK_LEN: i32 = 16;
+const CTX_MEMORY: i32 = 24;
+const CTX_MEMORY_LEN: i32 = 32;
+const CTX_IP: i32 = 40;
+const CTX_EXECUTED: i32 = 48;
+
+type Entry = unsafe extern "sysv64" fn(*mut Context);
+
+pub(crate) struct Block {
+    entry: Entry,
+    /// The most instructions a single run of the block can execute.
+    pub max_executed: u64,
+    _code: ExecutableMemory,
+}
+
+impl Block {
+    /// Runs the block on `ctx`.
+    ///
+    /// # Safety
+    ///
+    /// The pointers and lengths in `ctx` must describe the VM's live stack and memory.
+    pub unsafe fn run(&self, ctx: &mut Context) {
+        (self.entry)(ctx)
+    }
+}
+
+enum State {
+    Cold(u32),
+    Compiled(Block),
+    /// Nothing at this address can be compiled.
+    Never,
+}
+
+pub(crate) struct Jit {
+    states: Vec<State>,
+}
+
+impl Jit {
+    pub fn new(code_len: usize) -> Self {
+        Jit {
+            states: (0..code_len).map(|_| State::Cold(0)).collect(),
+        }
+    }
+
+    /// Returns the block starting at `ip`, compiling it if the address has become hot.
+    pub fn block_at(&mut self, ip: usize, code: &[Op<i32>], memory_len: usize) -> Option<&Block> {
+        let state = self.states.get_mut(ip)?;
+        if let State::Cold(count) = state {
+            *count += 1;
+            if *count < HOT_THRESHOLD {
+                return None;
+            }
+            *state = match compile(code, ip, memory_len) {
+                Some(block) => State::Compiled(block),
+                None => State::Never,
+            };
+        }
+        match state {
+            State::Compiled(block) => Some(block),
+            _ => None,
+        }
+    }
+}
+
+fn compile(code: &[Op<i32>], start: usize, memory_len: usize) -> Option<Block> {
+    let mut asm = Assembler::new();
+    let mut ip = start;
+    let mut executed = 0;
+    loop {
+        if ip >= code.len() || ip - start >= MAX_BLOCK_WORDS {
+            asm.exit(ip, executed);
+            break;
+        }
+        let op = code[ip];
+        let words = if op.is_fused() || matches!(op, Op::PushNext(_)) {
+            2
+        } else {
+            1
+        };
+        if !asm.op(op, ip, executed, memory_len) {
+            if executed == 0 {
+                return None;
+            }
+            asm.exit(ip, executed);
+            break;
+        }
+        ip += words;
+        // a fused op counts as both of its instructions
+        executed += if op.is_fused() { 2 } else { 1 };
+        if matches!(op, Op::JmpTo(_) | Op::JumpTo(..)) {
+            break;
+        }
+    }
+    let (bytes, entry) = asm.finish();
+    let memory = ExecutableMemory::new(&bytes)?;
+    // SAFETY: `entry` is the offset of the prologue of a function following the System V
+    // calling convention, and `memory` is kept alive in the block alongside the pointer.
+    let entry = unsafe { std::mem::transmute::<*const u8, Entry>(memory.ptr.add(entry)) };
+    Some(Block {
+        entry,
+        max_executed: executed,
+        _code: memory,
+    })
+}
+
+// x86-64 registers
+const RAX: u8 = 0;
+const RCX: u8 = 1;
+const RDX: u8 = 2;
+const RBX: u8 = 3;
+const RDI: u8 = 7;
+const R12: u8 = 12;
+const R13: u8 = 13;
+const R14: u8 = 14;
+const R15: u8 = 15;
+
+// x86-64 condition codes
+const CC_B: u8 = 0x2;
+const CC_AE: u8 = 0x3;
+const CC_E: u8 = 0x4;
+const CC_NE: u8 = 0x5;
+const CC_BE: u8 = 0x6;
+const CC_L: u8 = 0xC;
+const CC_GE: u8 = 0xD;
+const CC_LE: u8 = 0xE;
+const CC_G: u8 = 0xF;
+
+/// The size of the code emitted by [`Assembler::exit`].
+const EXIT_SIZE: i8 = 15;
+
+/// A memory operand `[base + index * scale + disp]`.
+#[derive(Clone, Copy)]
+struct Mem {
+    base: u8,
+    index: Option<(u8, u8)>,
+    disp: i32,
+}
+
+/// The stack slot `offset` cells above the top of the stack, `-1` being the top value.
+fn stack_slot(offset: i32) -> Mem {
+    Mem {
+        base: RBX,
+        index: Some((R12, 4)),
+        disp: offset * 4,
+    }
+}
+
+const TOP: i32 = -1;
+const SECOND: i32 = -2;
+const ABOVE: i32 = 0;
+
+/// Emits a block function. Register use inside a block:
+///
+/// - `rdi` the [`Context`]
+/// - `rbx` the stack base, `r12` the stack pointer, `r13` the stack length
+/// - `r14` the memory base, `r15` the memory length
+/// - `rax`, `rcx` and `rdx` scratch
+///
+/// Every exit jumps to a shared epilogue, placed first so its address is known, with the
+/// address to continue at in `eax` and the number of executed instructions in `edx`.
+struct Assembler {
+    bytes: Vec<u8>,
+    entry: usize,
+}
+
+impl Assembler {
+    fn new() -> Self {
+        let mut asm = Assembler {
+            bytes: Vec::new(),
+            entry: 0,
+        };
+
+        // epilogue
+        asm.mem(true, &[0x89], R12, ctx_field(CTX_SP));
+        asm.mem(true, &[0x89], RAX, ctx_field(CTX_IP));
+        asm.mem(true, &[0x89], RDX, ctx_field(CTX_EXECUTED));
+        asm.emit(&[0x41, 0x5F, 0x41, 0x5E, 0x41, 0x5D, 0x41, 0x5C, 0x5B, 0xC3]);
+
+        // prologue
+        asm.entry = asm.bytes.len();
+        asm.emit(&[0x53, 0x41, 0x54, 0x41, 0x55, 0x41, 0x56, 0x41, 0x57]);
+        asm.mem(true, &[0x8B], RBX, ctx_field(CTX_STACK));
+        asm.mem(true, &[0x8B], R12, ctx_field(CTX_SP));
+        asm.mem(true, &[0x8B], R13, ctx_field(CTX_STACK_LEN));
+        asm.mem(true, &[0x8B], R14, ctx_field(CTX_MEMORY));
+        asm.mem(true, &[0x8B], R15, ctx_field(CTX_MEMORY_LEN));
+        asm
+    }
+
+    fn finish(self) -> (Vec<u8>, usize) {
+        (self.bytes, self.entry)
+    }
+
+    /// Emits `op` at `ip`, returning `false` if it cannot be compiled.
+    fn op(&mut self, op: Op<i32>, ip: usize, executed: u64, memory_len: usize) -> bool {
+        let bail = (ip, executed);
+        match op {
+            Op::Push(v) | Op::PushNext(v) => {
+                self.need_space(1, bail);
+                self.mem_imm(0xC7, 0, stack_slot(ABOVE), v);
+                self.adjust_sp(1);
+            }
+            Op::Add => self.binary_mem(0x01, bail),
+            Op::Sub => self.binary_mem(0x29, bail),
+            Op::And => self.binary_mem(0x21, bail),
+            Op::Or => self.binary_mem(0x09, bail),
+            Op::Xor => self.binary_mem(0x31, bail),
+            Op::Mul => {
+                self.need_size(2, bail);
+                self.load(RAX, stack_slot(SECOND));
+                self.mem(false, &[0x0F, 0xAF], RAX, stack_slot(TOP));
+                self.store(stack_slot(SECOND), RAX);
+                self.adjust_sp(-1);
+            }
+            Op::Div | Op::Mod => {
+                self.need_size(2, bail);
+                self.load(RCX, stack_slot(TOP));
+                // division by zero and by -1, which may overflow, are left to the interpreter
+                self.emit(&[0x85, 0xC9]); // test ecx, ecx
+                self.guard(CC_NE, bail);
+                self.emit(&[0x83, 0xF9, 0xFF]); // cmp ecx, -1
+                self.guard(CC_NE, bail);
+                self.load(RAX, stack_slot(SECOND));
+                self.emit(&[0x99, 0xF7, 0xF9]); // cdq; idiv ecx
+                let result = if op == Op::Div { RAX } else { RDX };
+                self.store(stack_slot(SECOND), result);
+                self.adjust_sp(-1);
+            }
+            Op::Neg => self.unary_mem(0xF7, 3, bail),
+            Op::Not => self.unary_mem(0xF7, 2, bail),
+            Op::Inc => self.unary_mem(0xFF, 0, bail),
+            Op::Dec => self.unary_mem(0xFF, 1, bail),
+            Op::Shl | Op::Shr => {
+                self.need_size(2, bail);
+                self.load(RCX, stack_slot(TOP));
+                // shl/sar by cl take the count modulo 32 like the interpreter
+                let ext = if op == Op::Shl { 4 } else { 7 };
+                self.mem(false, &[0xD3], ext, stack_slot(SECOND));
+                self.adjust_sp(-1);
+            }
+            Op::Pop => {
+                self.need_size(1, bail);
+                self.adjust_sp(-1);
+            }
+            Op::Dup => {
+                self.need_space(1, bail);
+                self.need_size(1, bail);
+                self.load(RAX, stack_slot(TOP));
+                self.store(stack_slot(ABOVE), RAX);
+                self.adjust_sp(1);
+            }
+            Op::Swp => {
+                self.need_size(2, bail);
+                self.load(RAX, stack_slot(TOP));
+                self.load(RCX, stack_slot(SECOND));
+                self.store(stack_slot(TOP), RCX);
+                self.store(stack_slot(SECOND), RAX);
+            }
+            Op::Ovr => {
+                self.need_size(2, bail);
+                self.need_space(1, bail);
+                self.load(RAX, stack_slot(SECOND));
+                self.store(stack_slot(ABOVE), RAX);
+                self.adjust_sp(1);
+            }
+            Op::Load => {
+                self.need_size(1, bail);
+                self.memory_address(stack_slot(TOP), bail);
+                self.load(RCX, memory_cell());
+                self.store(stack_slot(TOP), RCX);
+            }
+            Op::Stor => {
+                self.need_size(2, bail);
+                self.memory_address(stack_slot(TOP), bail);
+                self.load(RCX, stack_slot(SECOND));
+                self.store(memory_cell(), RCX);
+                self.adjust_sp(-2);
+            }
+            Op::LoadFrom(addr) => {
+                let Some(cell) = constant_cell(addr, memory_len) else {
+                    return false;
+                };
+                self.need_space(1, bail);
+                self.load(RAX, cell);
+                self.store(stack_slot(ABOVE), RAX);
+                self.adjust_sp(1);
+            }
+            Op::StorTo(addr) => {
+                let Some(cell) = constant_cell(addr, memory_len) else {
+                    return false;
+                };
+                self.need_space(1, bail);
+                self.need_size(1, bail);
+                self.load(RAX, stack_slot(TOP));
+                self.store(cell, RAX);
+                self.adjust_sp(-1);
+            }
+            Op::Nop => {}
+            Op::JmpTo(addr) => {
+                self.need_space(1, bail);
+                self.exit(addr, executed + 2);
+            }
+            Op::JumpTo(cond, addr) => {
+                self.need_space(1, bail);
+                self.need_size(2, bail);
+                self.load(RAX, stack_slot(TOP));
+                self.mem(false, &[0x3B], RAX, stack_slot(SECOND)); // cmp eax, second
+                self.adjust_sp(-2);
+                self.guard(condition_code(cond) ^ 1, (addr, executed + 2));
+                self.exit(ip + 2, executed + 2);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// `second op= top`, popping the top.
+    fn binary_mem(&mut self, opcode: u8, bail: (usize, u64)) {
+        self.need_size(2, bail);
+        self.load(RAX, stack_slot(TOP));
+        self.mem(false, &[opcode], RAX, stack_slot(SECOND));
+        self.adjust_sp(-1);
+    }
+
+    /// Applies a unary instruction `opcode /ext` to the top of the stack.
+    fn unary_mem(&mut self, opcode: u8, ext: u8, bail: (usize, u64)) {
+        self.need_size(1, bail);
+        self.mem(false, &[opcode], ext, stack_slot(TOP));
+    }
+
+    /// Exits unless there are at least `n` values on the stack.
+    fn need_size(&mut self, n: i8, bail: (usize, u64)) {
+        self.emit(&[0x49, 0x83, 0xFC, n as u8]); // cmp r12, n
+        self.guard(CC_AE, bail);
+    }
+
+    /// Exits unless there is room for `n` more values on the stack.
+    fn need_space(&mut self, n: i8, bail: (usize, u64)) {
+        self.emit(&[0x49, 0x8D, 0x44, 0x24, n as u8]); // lea rax, [r12 + n]
+        self.emit(&[0x4C, 0x39, 0xE8]); // cmp rax, r13
+        self.guard(CC_BE, bail);
+    }
+
+    /// Sign extends the address at `src` into `rax`, exiting unless it is a valid memory address.
+    fn memory_address(&mut self, src: Mem, bail: (usize, u64)) {
+        self.mem(true, &[0x63], RAX, src); // movsxd rax, src
+        self.emit(&[0x4C, 0x39, 0xF8]); // cmp rax, r15
+        self.guard(CC_B, bail);
+    }
+
+    /// Continues if condition `cc` holds, otherwise exits to `target`.
+    fn guard(&mut self, cc: u8, target: (usize, u64)) {
+        self.emit(&[0x70 | cc, EXIT_SIZE as u8]);
+        self.exit(target.0, target.1);
+    }
+
+    /// Returns from the block, continuing at `ip` after `executed` instructions.
+    fn exit(&mut self, ip: usize, executed: u64) {
+        self.emit(&[0xB8]);
+        self.emit(&(ip as u32).to_le_bytes());
+        self.emit(&[0xBA]);
+        self.emit(&(executed as u32).to_le_bytes());
+        let rel = -(self.bytes.len() as i32 + 5);
+        self.emit(&[0xE9]);
+        self.emit(&rel.to_le_bytes());
+    }
+
+    /// `lea r12, [r12 + delta]`, which leaves the flags alone.
+    fn adjust_sp(&mut self, delta: i8) {
+        self.emit(&[0x4D, 0x8D, 0x64, 0x24, delta as u8]);
+    }
+
+    fn load(&mut self, reg: u8, src: Mem) {
+        self.mem(false, &[0x8B], reg, src);
+    }
+
+    fn store(&mut self, dst: Mem, reg: u8) {
+        self.mem(false, &[0x89], reg, dst);
+    }
+
+    fn mem_imm(&mut self, opcode: u8, ext: u8, dst: Mem, imm: i32) {
+        self.mem(false, &[opcode], ext, dst);
+        self.emit(&imm.to_le_bytes());
+    }
+
+    /// Emits `opcode` with a register and a memory operand, always in SIB form with a 32-bit
+    /// displacement.
+    fn mem(&mut self, wide: bool, opcode: &[u8], reg: u8, m: Mem) {
+        let (index, scale) = m.index.unwrap_or((0b100, 1));
+        let rex = 0x40
+            | (wide as u8) << 3
+            | (reg >> 3) << 2
+            | (m.index.map_or(0, |(i, _)| i >> 3)) << 1
+            | (m.base >> 3);
+        if rex != 0x40 {
+            self.emit(&[rex]);
+        }
+        self.emit(opcode);
+        let ss = match scale {
+            1 => 0,
+            2 => 1,
+            4 => 2,
+            _ => 3,
+        };
+        self.emit(&[
+            0x80 | (reg & 7) << 3 | 0b100,
+            ss << 6 | (index & 7) << 3 | (m.base & 7),
+        ]);
+        self.emit(&m.disp.to_le_bytes());
+    }
+
+    fn emit(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+}
+
+fn ctx_field(offset: i32) -> Mem {
+    Mem {
+        base: RDI,
+        index: None,
+        disp: offset,
+    }
+}
+
+/// The memory cell at the address in `rax`.
+fn memory_cell() -> Mem {
+    Mem {
+        base: R14,
+        index: Some((RAX, 4)),
+        disp: 0,
+    }
+}
+
+/// The memory cell at a literal address, if it is valid and can be encoded.
+fn constant_cell(addr: i32, memory_len: usize) -> Option<Mem> {
+    if addr < 0 || addr as usize >= memory_len {
+        return None;
+    }
+    Some(Mem {
+        base: R14,
+        index: None,
+        disp: addr.checked_mul(4)?,
+    })
+}
+
+fn condition_code(cond: Cond) -> u8 {
+    match cond {
+        Cond::Eq => CC_E,
+        Cond::Ne => CC_NE,
+        Cond::Gt => CC_G,
+        Cond::Ge => CC_GE,
+        Cond::Lt => CC_L,
+        Cond::Le => CC_LE,
+    }
+}
+
+/// A read-only, executable copy of generated code.
+struct ExecutableMemory {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl ExecutableMemory {
+    fn new(bytes: &[u8]) -> Option<Self> {
+        let len = bytes.len();
+        // SAFETY: a fresh anonymous mapping is written to only within its bounds, and made
+        // executable only after it is no longer writable.
+        unsafe {
+            let ptr = libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            if ptr == libc::MAP_FAILED {
+                return None;
+            }
+            let memory = ExecutableMemory {
+                ptr: ptr as *mut u8,
+                len,
+            };
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), memory.ptr, len);
+            if libc::mprotect(ptr, len, libc::PROT_READ | libc::PROT_EXEC) != 0 {
+                return None;
+            }
+            Some(memory)
+        }
+    }
+}
+
+impl Drop for ExecutableMemory {
+    fn drop(&mut self) {
+        // SAFETY: the mapping was created in `new` and is no longer referenced.
+        unsafe {
+            libc::munmap(self.ptr as *mut libc::c_void, self.len);
+        }
+    }
+}
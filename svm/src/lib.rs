@@ -1 +1,18 @@
+mod decode;
+pub mod device;
+pub mod disasm;
 pub mod instructions;
+pub mod io;
+#[cfg(feature = "jit")]
+mod jit;
+pub mod profile;
+pub mod program;
+pub mod snapshot;
+pub mod source_map;
+pub mod symbols;
+pub mod trace;
+mod vm;
+pub mod word;
+
+pub use vm::{ErrorKind, ExitReason, VMConfig, VMError, MAX_EXIT_STATUS, VM};
+pub use word::Word;
@@ -1,17 +1,80 @@
-use log::error;
-use vm::VM;
+use std::io::Write;
+use std::process::ExitCode;
+
+use debugger::Debugger;
+use log::{error, info};
+use svm::device::{Console, Device, Random, Timer};
+use svm::io::StdIo;
+use svm::profile::Profiler;
+use svm::program::{self, Program};
+use svm::snapshot::{self, Snapshot};
+use svm::source_map::SourceMap;
+use svm::symbols::SymbolTable;
+use svm::trace::{TraceFormat, Tracer};
+use svm::{ExitReason, VMConfig, VMError, Word, VM};
 
 use simplelog::{Config, LevelFilter, TermLogger, TerminalMode};
 
-mod vm;
+mod debugger;
 
-fn main() {
-    let args: Vec<String> = std::env::args().collect();
+const USAGE: &str = "usage: svm [debug|resume] [--stack-size N] [--memory-size N] \
+                     [--growable-stack] [--call-stack-size N] [--max-steps N] [--trace] \
+                     [--trace-file PATH] [--trace-format text|json] [--snapshot PATH] \
+                     [--checkpoint-every N] [--profile] [--profile-collapsed PATH] \
+                     [--device timer|console|random@ADDR]... [--jit] [--legacy] [filename]";
 
-    if args.len() != 2 {
-        eprintln!("usage: svm [filename]");
-        return;
-    }
+// Exit codes besides `ExitReason::exit_code` once the program stops and `ErrorKind::exit_code`
+// if it fails. Like those, they are above `MAX_EXIT_STATUS`, which programs cannot exit with.
+/// Returned for invalid command line arguments.
+const USAGE_EXIT_CODE: u8 = 64;
+/// Returned when the program exceeds `--max-steps`.
+const STEP_LIMIT_EXIT_CODE: u8 = 65;
+/// Returned when the program or snapshot cannot be loaded.
+const LOAD_FAILED_EXIT_CODE: u8 = 66;
+/// Returned when the VM cannot be set up as requested, e.g. a device cannot be mapped.
+const SETUP_FAILED_EXIT_CODE: u8 = 67;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Run,
+    /// Run the program under the interactive debugger.
+    Debug,
+    /// Continue a program from a snapshot file.
+    Resume,
+}
+
+struct Options {
+    mode: Mode,
+    filename: String,
+    config: VMConfig,
+    trace: bool,
+    trace_file: Option<String>,
+    trace_format: TraceFormat,
+    /// Where to save the VM state if it runs out of steps or reaches a checkpoint.
+    snapshot: Option<String>,
+    checkpoint_every: Option<u64>,
+    /// Print a profile once the program stops.
+    profile: bool,
+    /// Where to write the profile as collapsed stacks.
+    profile_collapsed: Option<String>,
+    /// Devices to map, by name and address.
+    devices: Vec<(String, usize)>,
+    /// Compile hot code to native code.
+    #[cfg(feature = "jit")]
+    jit: bool,
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{e}");
+            eprintln!("{USAGE}");
+            return ExitCode::from(USAGE_EXIT_CODE);
+        }
+    };
 
     TermLogger::init(
         LevelFilter::Info,
@@ -21,17 +84,311 @@ fn main() {
     )
     .unwrap();
 
-    let filename = &args[1];
-    run(filename);
+    run(&options)
 
     // run("examples/hello/hello");
 }
 
-fn run(filename: &str) {
-    let mut vm = VM::new();
-    if let Err(e) = vm.load(filename) {
-        error!("unable to load program: {e}");
-        return;
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let (mode, args) = match args.split_first() {
+        Some((cmd, rest)) if cmd == "debug" && !rest.is_empty() => (Mode::Debug, rest),
+        Some((cmd, rest)) if cmd == "resume" && !rest.is_empty() => (Mode::Resume, rest),
+        _ => (Mode::Run, args),
+    };
+    let mut filename = None;
+    let mut config = VMConfig::default();
+    let mut trace = false;
+    let mut trace_file = None;
+    let mut trace_format = TraceFormat::Text;
+    let mut snapshot = None;
+    let mut checkpoint_every = None;
+    let mut profile = false;
+    let mut profile_collapsed = None;
+    let mut devices = Vec::new();
+    #[cfg(feature = "jit")]
+    let mut jit = false;
+    // the first option that a snapshot overrides, since it brings its own configuration
+    let mut snapshot_option = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if matches!(
+            arg.as_str(),
+            "--stack-size"
+                | "--memory-size"
+                | "--growable-stack"
+                | "--call-stack-size"
+                | "--legacy"
+        ) {
+            snapshot_option.get_or_insert(arg);
+        }
+        match arg.as_str() {
+            "--stack-size" => config.stack_size = parse_value(arg, args.next())?,
+            "--memory-size" => config.memory_size = parse_value(arg, args.next())?,
+            "--growable-stack" => config.growable_stack = true,
+            "--call-stack-size" => config.call_stack_size = parse_value(arg, args.next())?,
+            "--max-steps" => config.max_steps = Some(parse_value(arg, args.next())?),
+            "--legacy" => config.legacy_format = true,
+            "--trace" => trace = true,
+            "--trace-file" => {
+                trace = true;
+                trace_file = Some(parse_value(arg, args.next())?);
+            }
+            "--trace-format" => {
+                trace_format = match args.next().map(String::as_str) {
+                    Some("text") => TraceFormat::Text,
+                    Some("json") => TraceFormat::Json,
+                    _ => return Err("'--trace-format' must be 'text' or 'json'".to_string()),
+                }
+            }
+            "--snapshot" => snapshot = Some(parse_value(arg, args.next())?),
+            "--checkpoint-every" => checkpoint_every = Some(parse_value(arg, args.next())?),
+            "--profile" => profile = true,
+            "--profile-collapsed" => profile_collapsed = Some(parse_value(arg, args.next())?),
+            "--device" => devices.push(parse_device(args.next())?),
+            #[cfg(feature = "jit")]
+            "--jit" => jit = true,
+            #[cfg(not(feature = "jit"))]
+            "--jit" => return Err("svm was built without the 'jit' feature".to_string()),
+            _ if arg.starts_with("--") => return Err(format!("unknown option '{arg}'")),
+            _ if filename.is_none() => filename = Some(arg.clone()),
+            _ => return Err(format!("unexpected argument '{arg}'")),
+        }
+    }
+
+    let filename = filename.ok_or("missing filename")?;
+    if let (Mode::Resume, Some(option)) = (mode, snapshot_option) {
+        return Err(format!("'{option}' cannot be used with 'resume'"));
+    }
+    if checkpoint_every.is_some() && snapshot.is_none() {
+        return Err("'--checkpoint-every' requires '--snapshot'".to_string());
+    }
+    if checkpoint_every == Some(0) {
+        return Err("'--checkpoint-every' must be positive".to_string());
+    }
+    Ok(Options {
+        mode,
+        filename,
+        config,
+        trace,
+        trace_file,
+        trace_format,
+        snapshot,
+        checkpoint_every,
+        profile,
+        profile_collapsed,
+        devices,
+        #[cfg(feature = "jit")]
+        jit,
+    })
+}
+
+/// Parses a `NAME@ADDR` device mapping.
+fn parse_device(value: Option<&String>) -> Result<(String, usize), String> {
+    let value = value.ok_or("missing value for '--device'")?;
+    let invalid = || format!("invalid device '{value}', expected e.g. 'timer@1024'");
+    let (name, addr) = value.split_once('@').ok_or_else(invalid)?;
+    if !matches!(name, "timer" | "console" | "random") {
+        return Err(format!("unknown device '{name}'"));
+    }
+    let addr = addr.parse().map_err(|_| invalid())?;
+    Ok((name.to_string(), addr))
+}
+
+fn parse_value<T: std::str::FromStr>(option: &str, value: Option<&String>) -> Result<T, String> {
+    let value = value.ok_or(format!("missing value for '{option}'"))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{value}' for '{option}'"))
+}
+
+/// Runs the program or snapshot with the word size its file declares.
+fn run(options: &Options) -> ExitCode {
+    let word_bits = if options.mode == Mode::Resume {
+        std::fs::File::open(&options.filename).and_then(snapshot::word_bits)
+    } else {
+        std::fs::read(&options.filename).map(|bytes| program::word_bits(&bytes))
+    };
+    match word_bits {
+        Ok(64) => run_with::<i64>(options),
+        // a file that is not 32-bit either fails to load with an error saying why
+        _ => run_with::<i32>(options),
+    }
+}
+
+fn run_with<W: Word>(options: &Options) -> ExitCode {
+    let mut vm = VM::<StdIo, W>::with_config_and_io(options.config, StdIo::new());
+    let mut symbols = None;
+    let mut source_map = None;
+    if options.mode == Mode::Resume {
+        match Snapshot::load(&options.filename) {
+            Ok(snapshot) => vm.restore(snapshot),
+            Err(e) => {
+                error!("unable to load snapshot: {e}");
+                return ExitCode::from(LOAD_FAILED_EXIT_CODE);
+            }
+        }
+        // the budget the snapshot was taken with does not carry over
+        vm.set_fuel(options.config.max_steps);
+    } else {
+        match load_program(&options.filename, &options.config) {
+            Ok(mut program) => {
+                symbols = program.symbols.take();
+                source_map = program.source_map.take();
+                vm.load_program(program);
+            }
+            Err(e) => {
+                error!("unable to load program: {e}");
+                return ExitCode::from(LOAD_FAILED_EXIT_CODE);
+            }
+        }
+    }
+    let symbols = symbols.unwrap_or_else(|| load_symbols(&options.filename));
+    if options.trace {
+        let tracer = match &options.trace_file {
+            Some(path) => match Tracer::file(path, options.trace_format) {
+                Ok(tracer) => tracer,
+                Err(e) => {
+                    error!("unable to create trace file: {e}");
+                    return ExitCode::from(SETUP_FAILED_EXIT_CODE);
+                }
+            },
+            None => Tracer::stderr(options.trace_format),
+        };
+        let tracer = match &source_map {
+            Some(map) => tracer.with_source_map(map.clone()),
+            None => tracer,
+        };
+        vm.set_tracer(Some(tracer));
+    }
+    for (name, addr) in &options.devices {
+        let device: Box<dyn Device> = match name.as_str() {
+            "timer" => Box::new(Timer::new()),
+            "console" => Box::new(Console::new()),
+            _ => Box::new(Random::new(random_seed())),
+        };
+        if let Err(e) = vm.map_device(*addr, device) {
+            error!("unable to map {name}: {e}");
+            return ExitCode::from(SETUP_FAILED_EXIT_CODE);
+        }
+    }
+    if options.profile || options.profile_collapsed.is_some() {
+        vm.set_profiler(Some(Profiler::new()));
+    }
+    #[cfg(feature = "jit")]
+    if options.jit {
+        vm.enable_jit();
+    }
+    if options.mode == Mode::Debug {
+        return debug(vm, symbols, source_map);
+    }
+    let result = execute(&mut vm, options);
+    if let Some(profiler) = vm.take_profiler() {
+        write_profile(&profiler, vm.program(), &symbols, options);
+    }
+    match result {
+        Ok(reason) => match reason.exit_code() {
+            Some(code) => ExitCode::from(code),
+            None => {
+                error!("step limit exceeded at {}", vm.ip());
+                save_snapshot(&vm, options);
+                ExitCode::from(STEP_LIMIT_EXIT_CODE)
+            }
+        },
+        Err(e) => {
+            match source_map.as_ref().and_then(|map| map.describe(e.ip)) {
+                Some(source) => error!("{source}: {e}"),
+                None => error!("{e}"),
+            }
+            ExitCode::from(e.kind.exit_code())
+        }
+    }
+}
+
+/// Runs the program, saving a snapshot at every checkpoint.
+fn execute<W: Word>(vm: &mut VM<StdIo, W>, options: &Options) -> Result<ExitReason, VMError> {
+    let Some(every) = options.checkpoint_every else {
+        return vm.run();
+    };
+    loop {
+        let reason = vm.run_for(every)?;
+        if reason != ExitReason::Yielded || vm.fuel() == Some(0) {
+            return Ok(reason);
+        }
+        save_snapshot(vm, options);
+    }
+}
+
+fn save_snapshot<W: Word>(vm: &VM<StdIo, W>, options: &Options) {
+    if let Some(path) = &options.snapshot {
+        match vm.snapshot().save(path) {
+            Ok(()) => info!("saved snapshot to [{path}]"),
+            Err(e) => error!("unable to save snapshot: {e}"),
+        }
+    }
+}
+
+fn random_seed() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64)
+}
+
+fn write_profile<W: Word>(
+    profiler: &Profiler,
+    program: &[W],
+    symbols: &SymbolTable,
+    options: &Options,
+) {
+    if options.profile {
+        if let Err(e) = profiler.report(std::io::stderr().lock(), program, Some(symbols)) {
+            error!("unable to write profile: {e}");
+        }
+    }
+    if let Some(path) = &options.profile_collapsed {
+        let written = std::fs::File::create(path)
+            .map(std::io::BufWriter::new)
+            .and_then(|mut file| {
+                profiler.write_collapsed(&mut file, Some(symbols))?;
+                file.flush()
+            });
+        match written {
+            Ok(()) => info!("wrote collapsed stacks to [{path}]"),
+            Err(e) => error!("unable to write collapsed stacks: {e}"),
+        }
+    }
+}
+
+fn load_program<W: Word>(filename: &str, config: &VMConfig) -> std::io::Result<Program<W>> {
+    info!("loading program from file [{filename}]");
+    if config.legacy_format {
+        Program::load_legacy(filename)
+    } else {
+        Program::load(filename)
+    }
+}
+
+/// Loads the symbols the assembler wrote next to `filename`, for programs without embedded
+/// ones.
+fn load_symbols(filename: &str) -> SymbolTable {
+    let path = SymbolTable::sidecar_path(filename);
+    match SymbolTable::load(&path) {
+        Ok(symbols) => symbols,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => SymbolTable::new(),
+        Err(e) => {
+            error!("unable to load symbols: {e}");
+            SymbolTable::new()
+        }
+    }
+}
+
+fn debug<W: Word>(
+    vm: VM<StdIo, W>,
+    symbols: SymbolTable,
+    source_map: Option<SourceMap>,
+) -> ExitCode {
+    if let Err(e) = Debugger::new(vm, symbols, source_map).run() {
+        error!("debugger io error: {e}");
     }
-    vm.run();
+    ExitCode::SUCCESS
 }
@@ -0,0 +1,229 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{self, Write};
+
+use crate::disasm::disassemble;
+use crate::instructions::{CALL, LOAD, RET, STOR};
+use crate::symbols::SymbolTable;
+use crate::trace::op_name;
+use crate::word::Word;
+
+/// The number of addresses listed in a report.
+const HOT_ADDRESSES: usize = 20;
+/// The name of the outermost frame in collapsed stacks.
+const ROOT_FRAME: &str = "main";
+
+/// Counts what the VM executes, for finding out where a program spends its time.
+///
+/// Besides counts per address and per opcode, the profiler follows `CALL` and `RET` to
+/// attribute every instruction to the chain of subroutines it ran in, which can be written in
+/// the collapsed stack format flamegraph tools read.
+#[derive(Default)]
+pub struct Profiler {
+    /// Executions per address.
+    counts: Vec<u64>,
+    opcodes: BTreeMap<&'static str, u64>,
+    total: u64,
+    max_depth: usize,
+    touched: BTreeSet<usize>,
+    /// Active subroutines as a tree of frames, `0` being the root.
+    frames: Vec<Frame>,
+    children: HashMap<(usize, usize), usize>,
+    current: usize,
+    /// Executions per frame and address.
+    samples: HashMap<(usize, usize), u64>,
+}
+
+struct Frame {
+    parent: usize,
+    /// The address of the first instruction of the subroutine.
+    entry: usize,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler {
+            frames: vec![Frame {
+                parent: 0,
+                entry: 0,
+            }],
+            ..Profiler::default()
+        }
+    }
+
+    /// Captures what is needed to profile `inst` before it is executed.
+    pub(crate) fn begin<W: Word>(
+        &self,
+        ip: usize,
+        inst: W,
+        stack: &[W],
+        memory_len: usize,
+    ) -> Sample {
+        let addr = match (inst.opcode(), stack.last()) {
+            (Some(LOAD | STOR), Some(&addr)) => Some(Into::<i64>::into(addr) as usize),
+            _ => None,
+        };
+        let addr = addr.filter(|&addr| addr < memory_len);
+        Sample {
+            ip,
+            inst: inst.into(),
+            addr,
+        }
+    }
+
+    /// Records an instruction that executed successfully, leaving `depth` values on the stack
+    /// and continuing at `next`.
+    pub(crate) fn finish(&mut self, sample: Sample, depth: usize, next: usize) {
+        let Sample { ip, inst, addr } = sample;
+        if ip >= self.counts.len() {
+            self.counts.resize(ip + 1, 0);
+        }
+        self.counts[ip] += 1;
+        *self.opcodes.entry(op_name(inst)).or_default() += 1;
+        self.total += 1;
+        self.max_depth = self.max_depth.max(depth);
+        if let Some(addr) = addr {
+            self.touched.insert(addr);
+        }
+        *self.samples.entry((self.current, ip)).or_default() += 1;
+
+        match inst.opcode() {
+            Some(CALL) => self.current = self.frame(self.current, next),
+            // a program resumed inside a subroutine may return past the root
+            Some(RET) => self.current = self.frames[self.current].parent,
+            _ => {}
+        }
+    }
+
+    fn frame(&mut self, parent: usize, entry: usize) -> usize {
+        let frames = &mut self.frames;
+        *self.children.entry((parent, entry)).or_insert_with(|| {
+            frames.push(Frame { parent, entry });
+            frames.len() - 1
+        })
+    }
+
+    /// The number of instructions executed.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// The number of times the instruction at `addr` was executed.
+    pub fn count(&self, addr: usize) -> u64 {
+        self.counts.get(addr).copied().unwrap_or(0)
+    }
+
+    /// The number of times each opcode was executed, by mnemonic.
+    pub fn opcodes(&self) -> &BTreeMap<&'static str, u64> {
+        &self.opcodes
+    }
+
+    /// The most values the stack held after an instruction.
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    /// The memory addresses read or written.
+    pub fn touched(&self) -> &BTreeSet<usize> {
+        &self.touched
+    }
+
+    /// Writes a human readable summary with the hottest addresses of `program`.
+    pub fn report<T: Write, W: Word>(
+        &self,
+        mut w: T,
+        program: &[W],
+        symbols: Option<&SymbolTable>,
+    ) -> io::Result<()> {
+        writeln!(w, "instructions executed  {}", self.total)?;
+        writeln!(w, "max stack depth        {}", self.max_depth)?;
+        write!(w, "memory cells touched   {}", self.touched.len())?;
+        match (self.touched.first(), self.touched.last()) {
+            (Some(first), Some(last)) => writeln!(w, " ({first}..={last})")?,
+            _ => writeln!(w)?,
+        }
+
+        let mut hot: Vec<(usize, u64)> = (self.counts.iter().copied().enumerate())
+            .filter(|&(_, count)| count > 0)
+            .collect();
+        hot.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        writeln!(w)?;
+        writeln!(w, "hot addresses:")?;
+        for &(addr, count) in hot.iter().take(HOT_ADDRESSES) {
+            let location = symbols.map_or(addr.to_string(), |s| s.location(addr));
+            let inst = if addr < program.len() {
+                disassemble(program, addr, symbols)
+            } else {
+                String::new()
+            };
+            writeln!(
+                w,
+                "{count:>12} {:>6.2}%  {location:<24} {inst}",
+                self.percent(count)
+            )?;
+        }
+
+        let mut opcodes: Vec<(&str, u64)> = self.opcodes.iter().map(|(&k, &v)| (k, v)).collect();
+        opcodes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        writeln!(w)?;
+        writeln!(w, "opcodes:")?;
+        for (name, count) in opcodes {
+            writeln!(w, "{count:>12} {:>6.2}%  {name}", self.percent(count))?;
+        }
+        Ok(())
+    }
+
+    /// Writes one `frame;frame;... count` line per distinct stack of subroutines.
+    ///
+    /// Subroutines are named after the label at their entry. With symbols, instructions are
+    /// further split by the label they belong to within their subroutine.
+    pub fn write_collapsed<W: Write>(
+        &self,
+        mut w: W,
+        symbols: Option<&SymbolTable>,
+    ) -> io::Result<()> {
+        let mut stacks: BTreeMap<String, u64> = BTreeMap::new();
+        for (&(frame, ip), &count) in &self.samples {
+            let mut stack = self.frame_names(frame, symbols);
+            let entry = self.frames[frame].entry;
+            if let Some((name, addr)) = symbols.and_then(|s| s.enclosing(ip)) {
+                if addr > entry || (frame == 0 && addr != entry) {
+                    stack.push(';');
+                    stack.push_str(name);
+                }
+            }
+            *stacks.entry(stack).or_default() += count;
+        }
+        for (stack, count) in stacks {
+            writeln!(w, "{stack} {count}")?;
+        }
+        Ok(())
+    }
+
+    /// The names of `frame` and the frames it was called from, outermost first.
+    fn frame_names(&self, mut frame: usize, symbols: Option<&SymbolTable>) -> String {
+        let mut names = Vec::new();
+        while frame != 0 {
+            let entry = self.frames[frame].entry;
+            names.push(
+                symbols
+                    .and_then(|s| s.labels_at(entry).first().cloned())
+                    .unwrap_or_else(|| entry.to_string()),
+            );
+            frame = self.frames[frame].parent;
+        }
+        names.push(ROOT_FRAME.to_string());
+        names.reverse();
+        names.join(";")
+    }
+
+    fn percent(&self, count: u64) -> f64 {
+        count as f64 * 100.0 / self.total.max(1) as f64
+    }
+}
+
+pub(crate) struct Sample {
+    ip: usize,
+    inst: i64,
+    /// The memory address accessed by `LOAD` or `STOR`, if valid.
+    addr: Option<usize>,
+}
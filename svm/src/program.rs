@@ -0,0 +1,263 @@
+use std::io::{self, Write};
+
+use crate::source_map::SourceMap;
+use crate::symbols::SymbolTable;
+use crate::word::Word;
+
+/// Marks a program file.
+pub const MAGIC: [u8; 4] = *b"SVM\0";
+pub const VERSION: u32 = 1;
+
+const HEADER_SIZE: usize = 20;
+/// The word size of legacy programs, which have no header to declare one.
+const LEGACY_WORD_BITS: u32 = 32;
+const SECTION_HEADER_SIZE: usize = 8;
+const CHECKSUM_SIZE: usize = 4;
+
+/// Section kinds.
+const CODE: u32 = 1;
+const DATA: u32 = 2;
+const SYMBOLS: u32 = 3;
+const SOURCE_MAP: u32 = 4;
+
+/// An assembled program together with the resources it declares.
+///
+/// On disk a program is a header of five little-endian `u32`s: [`MAGIC`], the format version,
+/// the word size in bits, the stack size and the memory size, where a size of zero means the
+/// program does not declare one. Sections follow, each a `u32` kind and a `u32` length in bytes
+/// before its contents:
+///
+/// 1. code, as little-endian words, which every program has,
+/// 2. initialized data, as little-endian words,
+/// 3. symbols, in the text form of [`SymbolTable`],
+/// 4. debug info, in the text form of [`SourceMap`].
+///
+/// The file ends with the CRC-32 of everything before it.
+///
+/// Older files are plain 32-bit code without a header. They are only read by
+/// [`from_legacy_bytes`](Program::from_legacy_bytes).
+///
+/// A program can only be read with the word size it was written with.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Program<W = i32> {
+    pub code: Vec<W>,
+    /// The contents of memory from address 0 when the program starts.
+    pub data: Vec<W>,
+    /// The labels of the assembly source, if the program was assembled with them.
+    pub symbols: Option<SymbolTable>,
+    /// Where the code came from in the assembly source, if the program was assembled with
+    /// debug info.
+    pub source_map: Option<SourceMap>,
+    /// The number of stack cells the program needs.
+    pub stack_size: Option<usize>,
+    /// The number of memory cells the program needs.
+    pub memory_size: Option<usize>,
+}
+
+impl<W: Word> Program<W> {
+    pub fn new(code: Vec<W>) -> Self {
+        Program {
+            code,
+            ..Program::default()
+        }
+    }
+
+    pub fn load(filename: &str) -> io::Result<Self> {
+        let bytes = std::fs::read(filename)?;
+        Program::from_bytes(&bytes)
+    }
+
+    /// Loads a program like [`load`](Program::load), also accepting the headerless format
+    /// written before the header was introduced.
+    pub fn load_legacy(filename: &str) -> io::Result<Self> {
+        let bytes = std::fs::read(filename)?;
+        Program::from_legacy_bytes(&bytes)
+    }
+
+    /// Reads a program, rejecting anything that is not a complete and intact program file of
+    /// the current version.
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        if !bytes.starts_with(&MAGIC) {
+            return Err(invalid_data("not an svm program"));
+        }
+        if bytes.len() < HEADER_SIZE {
+            return Err(invalid_data("truncated program header"));
+        }
+        let version = read_u32(bytes, 4);
+        if version != VERSION {
+            return Err(invalid_data(&format!(
+                "unsupported program version {version}"
+            )));
+        }
+        check_word_bits::<W>(read_u32(bytes, 8))?;
+        if bytes.len() < HEADER_SIZE + CHECKSUM_SIZE {
+            return Err(invalid_data("truncated program"));
+        }
+        let (contents, checksum) = bytes.split_at(bytes.len() - CHECKSUM_SIZE);
+        if crc32(contents) != read_u32(checksum, 0) {
+            return Err(invalid_data("program checksum mismatch"));
+        }
+
+        let mut program = Program {
+            stack_size: declared_size(read_u32(bytes, 12)),
+            memory_size: declared_size(read_u32(bytes, 16)),
+            ..Program::default()
+        };
+        let mut seen = Vec::new();
+        let mut rest = &contents[HEADER_SIZE..];
+        while !rest.is_empty() {
+            if rest.len() < SECTION_HEADER_SIZE {
+                return Err(invalid_data("truncated section header"));
+            }
+            let kind = read_u32(rest, 0);
+            let len = read_u32(rest, 4) as usize;
+            rest = &rest[SECTION_HEADER_SIZE..];
+            if len > rest.len() {
+                return Err(invalid_data("truncated section"));
+            }
+            let (section, next) = rest.split_at(len);
+            rest = next;
+            if seen.contains(&kind) {
+                return Err(invalid_data(&format!("duplicate section {kind}")));
+            }
+            seen.push(kind);
+            match kind {
+                CODE => program.code = words(section)?,
+                DATA => program.data = words(section)?,
+                SYMBOLS => {
+                    let text = std::str::from_utf8(section)
+                        .map_err(|_| invalid_data("symbols are not valid UTF-8"))?;
+                    program.symbols = Some(SymbolTable::from_text(text)?);
+                }
+                SOURCE_MAP => {
+                    let text = std::str::from_utf8(section)
+                        .map_err(|_| invalid_data("debug info is not valid UTF-8"))?;
+                    program.source_map = Some(SourceMap::from_text(text)?);
+                }
+                _ => return Err(invalid_data(&format!("unknown section {kind}"))),
+            }
+        }
+        if !seen.contains(&CODE) {
+            return Err(invalid_data("program has no code section"));
+        }
+        Ok(program)
+    }
+
+    /// Reads a program like [`from_bytes`](Program::from_bytes), also accepting the headerless
+    /// format written before the header was introduced.
+    pub fn from_legacy_bytes(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.starts_with(&MAGIC) {
+            return Program::from_bytes(bytes);
+        }
+        check_word_bits::<W>(LEGACY_WORD_BITS)?;
+        Ok(Program::new(words(bytes)?))
+    }
+
+    pub fn write<T: Write>(&self, mut w: T) -> io::Result<()> {
+        w.write_all(&self.to_bytes()?)
+    }
+
+    pub fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&W::BITS.to_le_bytes());
+        for size in [self.stack_size, self.memory_size] {
+            let size = u32::try_from(size.unwrap_or(0))
+                .map_err(|_| invalid_data("declared size does not fit in 32 bits"))?;
+            bytes.extend_from_slice(&size.to_le_bytes());
+        }
+
+        write_section(&mut bytes, CODE, &word_bytes(&self.code))?;
+        if !self.data.is_empty() {
+            write_section(&mut bytes, DATA, &word_bytes(&self.data))?;
+        }
+        if let Some(symbols) = &self.symbols {
+            let mut text = Vec::new();
+            symbols.write(&mut text)?;
+            write_section(&mut bytes, SYMBOLS, &text)?;
+        }
+        if let Some(source_map) = &self.source_map {
+            let mut text = Vec::new();
+            source_map.write(&mut text)?;
+            write_section(&mut bytes, SOURCE_MAP, &text)?;
+        }
+
+        let checksum = crc32(&bytes);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        Ok(bytes)
+    }
+}
+
+/// The word size in bits of the program in `bytes`, without checking the rest of the file.
+///
+/// Legacy programs, and anything else without a complete header, have 32-bit words.
+pub fn word_bits(bytes: &[u8]) -> u32 {
+    let sized = bytes.starts_with(&MAGIC) && bytes.len() >= HEADER_SIZE;
+    if sized && read_u32(bytes, 4) == VERSION {
+        read_u32(bytes, 8)
+    } else {
+        LEGACY_WORD_BITS
+    }
+}
+
+fn check_word_bits<W: Word>(word_bits: u32) -> io::Result<()> {
+    if word_bits != W::BITS {
+        return Err(invalid_data(&format!(
+            "program has {word_bits}-bit words, expected {}-bit",
+            W::BITS
+        )));
+    }
+    Ok(())
+}
+
+fn write_section(bytes: &mut Vec<u8>, kind: u32, contents: &[u8]) -> io::Result<()> {
+    let len = u32::try_from(contents.len())
+        .map_err(|_| invalid_data("section does not fit in 32 bits"))?;
+    bytes.extend_from_slice(&kind.to_le_bytes());
+    bytes.extend_from_slice(&len.to_le_bytes());
+    bytes.extend_from_slice(contents);
+    Ok(())
+}
+
+fn words<W: Word>(bytes: &[u8]) -> io::Result<Vec<W>> {
+    let size = W::BITS as usize / 8;
+    if !bytes.len().is_multiple_of(size) {
+        return Err(invalid_data(&format!(
+            "program size is not a multiple of {size} bytes"
+        )));
+    }
+    Ok(bytes.chunks_exact(size).map(W::read_le).collect())
+}
+
+fn word_bytes<W: Word>(words: &[W]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for word in words {
+        word.write_le(&mut bytes);
+    }
+    bytes
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+/// The CRC-32 used by zip and PNG.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+fn declared_size(size: u32) -> Option<usize> {
+    (size != 0).then_some(size as usize)
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
@@ -0,0 +1,213 @@
+use std::io::{self, Read, Write};
+
+use crate::vm::VMConfig;
+use crate::word::Word;
+
+/// Marks a snapshot file.
+pub const MAGIC: [u8; 4] = *b"SVMS";
+pub const VERSION: u32 = 2;
+
+/// The complete state of a [`VM`](crate::VM) apart from its I/O backend and tracer.
+///
+/// On disk a snapshot is [`MAGIC`], the format version and the word size in bits as a `u32`,
+/// followed by the fields below in order, all little-endian. Sizes and addresses are `u64`s,
+/// flags are bytes, optional values are a flag byte followed by the value if present, and
+/// sequences are a `u64` length followed by their elements. Version 1 snapshots have no word
+/// size or exit status and 32-bit words.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot<W = i32> {
+    pub config: VMConfig,
+    pub program: Vec<W>,
+    /// The live portion of the stack, bottom first.
+    pub stack: Vec<W>,
+    /// The number of cells allocated for the stack, which may have grown past the configured size.
+    pub stack_size: usize,
+    pub memory: Vec<W>,
+    pub call_stack: Vec<usize>,
+    pub fuel: Option<u64>,
+    pub ip: usize,
+    pub hf: bool,
+    pub rf: bool,
+    /// Added in version 2, `None` in version 1 snapshots.
+    pub exit_status: Option<i64>,
+}
+
+impl<W: Word> Snapshot<W> {
+    pub fn load(filename: &str) -> io::Result<Self> {
+        let file = std::fs::File::open(filename)?;
+        Snapshot::read(io::BufReader::new(file))
+    }
+
+    /// Writes the snapshot to `filename`, replacing any previous one only once it is complete.
+    pub fn save(&self, filename: &str) -> io::Result<()> {
+        let tmp = format!("{filename}.tmp");
+        let mut w = io::BufWriter::new(std::fs::File::create(&tmp)?);
+        self.write(&mut w)?;
+        w.into_inner()?.sync_all()?;
+        std::fs::rename(tmp, filename)
+    }
+
+    pub fn read<R: Read>(mut r: R) -> io::Result<Self> {
+        let (version, word_bits) = read_header(&mut r)?;
+        if word_bits != W::BITS {
+            return Err(invalid_data(format!(
+                "snapshot has {word_bits}-bit words, expected {}-bit",
+                W::BITS
+            )));
+        }
+        let config = VMConfig {
+            stack_size: read_usize(&mut r)?,
+            memory_size: read_usize(&mut r)?,
+            growable_stack: read_bool(&mut r)?,
+            max_stack_size: read_usize(&mut r)?,
+            call_stack_size: read_usize(&mut r)?,
+            max_steps: read_option(&mut r)?,
+            // only matters while loading a program
+            legacy_format: false,
+        };
+        Ok(Snapshot {
+            config,
+            program: read_words(&mut r)?,
+            stack: read_words(&mut r)?,
+            stack_size: read_usize(&mut r)?,
+            memory: read_words(&mut r)?,
+            call_stack: read_addresses(&mut r)?,
+            fuel: read_option(&mut r)?,
+            ip: read_usize(&mut r)?,
+            hf: read_bool(&mut r)?,
+            rf: read_bool(&mut r)?,
+            exit_status: match version {
+                1 => None,
+                _ => read_option(&mut r)?.map(|v| v as i64),
+            },
+        })
+    }
+
+    pub fn write<T: Write>(&self, mut w: T) -> io::Result<()> {
+        w.write_all(&MAGIC)?;
+        w.write_all(&VERSION.to_le_bytes())?;
+        w.write_all(&W::BITS.to_le_bytes())?;
+
+        let config = &self.config;
+        write_u64(&mut w, config.stack_size as u64)?;
+        write_u64(&mut w, config.memory_size as u64)?;
+        w.write_all(&[config.growable_stack as u8])?;
+        write_u64(&mut w, config.max_stack_size as u64)?;
+        write_u64(&mut w, config.call_stack_size as u64)?;
+        write_option(&mut w, config.max_steps)?;
+
+        write_words(&mut w, &self.program)?;
+        write_words(&mut w, &self.stack)?;
+        write_u64(&mut w, self.stack_size as u64)?;
+        write_words(&mut w, &self.memory)?;
+        write_u64(&mut w, self.call_stack.len() as u64)?;
+        for addr in &self.call_stack {
+            write_u64(&mut w, *addr as u64)?;
+        }
+        write_option(&mut w, self.fuel)?;
+        write_u64(&mut w, self.ip as u64)?;
+        w.write_all(&[self.hf as u8, self.rf as u8])?;
+        write_option(&mut w, self.exit_status.map(|v| v as u64))
+    }
+}
+
+/// The word size in bits of the snapshot read from `r`, without reading the rest of it.
+pub fn word_bits<R: Read>(mut r: R) -> io::Result<u32> {
+    read_header(&mut r).map(|(_, word_bits)| word_bits)
+}
+
+/// Reads the magic, the version and the word size.
+fn read_header<R: Read>(r: &mut R) -> io::Result<(u32, u32)> {
+    let mut magic = [0; 4];
+    r.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(invalid_data("not a snapshot file".to_string()));
+    }
+    match read_u32(r)? {
+        VERSION => Ok((VERSION, read_u32(r)?)),
+        1 => Ok((1, 32)),
+        version => Err(invalid_data(format!(
+            "unsupported snapshot version {version}"
+        ))),
+    }
+}
+
+fn read_u32<R: Read>(r: &mut R) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    r.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    r.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_usize<R: Read>(r: &mut R) -> io::Result<usize> {
+    let v = read_u64(r)?;
+    usize::try_from(v).map_err(|_| invalid_data(format!("size {v} is too large")))
+}
+
+fn read_bool<R: Read>(r: &mut R) -> io::Result<bool> {
+    let mut byte = [0; 1];
+    r.read_exact(&mut byte)?;
+    match byte[0] {
+        0 => Ok(false),
+        1 => Ok(true),
+        b => Err(invalid_data(format!("invalid flag {b}"))),
+    }
+}
+
+fn read_option<R: Read>(r: &mut R) -> io::Result<Option<u64>> {
+    if read_bool(r)? {
+        Ok(Some(read_u64(r)?))
+    } else {
+        Ok(None)
+    }
+}
+
+fn read_words<R: Read, W: Word>(r: &mut R) -> io::Result<Vec<W>> {
+    let len = read_usize(r)?;
+    let size = W::BITS as usize / 8;
+    let total =
+        (len.checked_mul(size)).ok_or_else(|| invalid_data(format!("size {len} is too large")))?;
+    let mut bytes = Vec::new();
+    r.take(total as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != total {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(bytes.chunks_exact(size).map(W::read_le).collect())
+}
+
+fn read_addresses<R: Read>(r: &mut R) -> io::Result<Vec<usize>> {
+    let len = read_usize(r)?;
+    (0..len).map(|_| read_usize(r)).collect()
+}
+
+fn write_u64<W: Write>(w: &mut W, v: u64) -> io::Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+
+fn write_option<W: Write>(w: &mut W, v: Option<u64>) -> io::Result<()> {
+    match v {
+        Some(v) => {
+            w.write_all(&[1])?;
+            write_u64(w, v)
+        }
+        None => w.write_all(&[0]),
+    }
+}
+
+fn write_words<T: Write, W: Word>(w: &mut T, words: &[W]) -> io::Result<()> {
+    write_u64(w, words.len() as u64)?;
+    let mut bytes = Vec::new();
+    for word in words {
+        word.write_le(&mut bytes);
+    }
+    w.write_all(&bytes)
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
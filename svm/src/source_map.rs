@@ -0,0 +1,95 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Write};
+
+/// A position in an assembly source file, both counting from 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// Maps code addresses to where in the assembly source they were assembled from.
+///
+/// The text form has a `file NAME` line followed by one `address line:column` line per
+/// address.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    file: String,
+    locations: BTreeMap<usize, SourceLocation>,
+}
+
+impl SourceMap {
+    pub fn new(file: &str) -> Self {
+        SourceMap {
+            file: file.to_string(),
+            locations: BTreeMap::new(),
+        }
+    }
+
+    pub fn from_text(text: &str) -> io::Result<Self> {
+        let mut lines = text.lines().filter(|l| !l.trim().is_empty());
+        let file = lines
+            .next()
+            .and_then(|l| l.strip_prefix("file "))
+            .ok_or_else(|| invalid_data("source map does not start with a file name"))?;
+        let mut map = SourceMap::new(file);
+        for line in lines {
+            let parsed = line.trim().split_once(' ').and_then(|(addr, loc)| {
+                let (l, c) = loc.split_once(':')?;
+                let location = SourceLocation {
+                    line: l.parse().ok()?,
+                    column: c.parse().ok()?,
+                };
+                Some((addr.parse().ok()?, location))
+            });
+            let (addr, location) =
+                parsed.ok_or_else(|| invalid_data(&format!("invalid source map line '{line}'")))?;
+            map.insert(addr, location);
+        }
+        Ok(map)
+    }
+
+    pub fn write<W: Write>(&self, mut w: W) -> io::Result<()> {
+        writeln!(w, "file {}", self.file)?;
+        for (addr, location) in &self.locations {
+            writeln!(w, "{addr} {location}")?;
+        }
+        Ok(())
+    }
+
+    /// The path of the source file, as given to the assembler.
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+
+    pub fn insert(&mut self, addr: usize, location: SourceLocation) {
+        self.locations.insert(addr, location);
+    }
+
+    pub fn get(&self, addr: usize) -> Option<SourceLocation> {
+        self.locations.get(&addr).copied()
+    }
+
+    /// The first address assembled from `line`.
+    pub fn address_of_line(&self, line: usize) -> Option<usize> {
+        self.locations
+            .iter()
+            .find_map(|(&addr, l)| (l.line == line).then_some(addr))
+    }
+
+    /// Formats the source position of `addr`, e.g. `fib.asm:12:5`.
+    pub fn describe(&self, addr: usize) -> Option<String> {
+        self.get(addr).map(|l| format!("{}:{l}", self.file))
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
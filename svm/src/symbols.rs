@@ -0,0 +1,100 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+
+/// Maps label names to code addresses and back.
+///
+/// The text form has one `address name` pair per line.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SymbolTable {
+    by_name: HashMap<String, usize>,
+    by_addr: BTreeMap<usize, Vec<String>>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        SymbolTable::default()
+    }
+
+    /// The path of the symbol file the assembler writes next to `program`.
+    pub fn sidecar_path(program: &str) -> String {
+        format!("{program}.sym")
+    }
+
+    pub fn load(filename: &str) -> io::Result<Self> {
+        let text = std::fs::read_to_string(filename)?;
+        SymbolTable::from_text(&text)
+    }
+
+    pub fn from_text(text: &str) -> io::Result<Self> {
+        let mut table = SymbolTable::new();
+        for line in text.lines().filter(|l| !l.trim().is_empty()) {
+            let (addr, name) = line
+                .trim()
+                .split_once(' ')
+                .ok_or_else(|| invalid_data(line))?;
+            let addr = addr.parse().map_err(|_| invalid_data(line))?;
+            table.insert(name.trim(), addr);
+        }
+        Ok(table)
+    }
+
+    pub fn write<W: Write>(&self, mut w: W) -> io::Result<()> {
+        for (addr, name) in self.iter() {
+            writeln!(w, "{addr} {name}")?;
+        }
+        Ok(())
+    }
+
+    pub fn insert(&mut self, name: &str, addr: usize) {
+        if let Some(old) = self.by_name.insert(name.to_string(), addr) {
+            if let Some(names) = self.by_addr.get_mut(&old) {
+                names.retain(|n| n != name);
+            }
+        }
+        self.by_addr.entry(addr).or_default().push(name.to_string());
+    }
+
+    pub fn address(&self, name: &str) -> Option<usize> {
+        self.by_name.get(name).copied()
+    }
+
+    /// The labels defined at `addr`.
+    pub fn labels_at(&self, addr: usize) -> &[String] {
+        self.by_addr.get(&addr).map_or(&[], Vec::as_slice)
+    }
+
+    /// The closest label at or before `addr` and its address.
+    pub fn enclosing(&self, addr: usize) -> Option<(&str, usize)> {
+        self.by_addr
+            .range(..=addr)
+            .rev()
+            .find_map(|(a, names)| names.first().map(|n| (n.as_str(), *a)))
+    }
+
+    /// Formats `addr` with the label it belongs to, if any, e.g. `17 <loop+3>`.
+    pub fn location(&self, addr: usize) -> String {
+        match self.enclosing(addr) {
+            Some((name, a)) if a == addr => format!("{addr} <{name}>"),
+            Some((name, a)) => format!("{addr} <{name}+{}>", addr - a),
+            None => addr.to_string(),
+        }
+    }
+
+    /// All symbols ordered by address.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.by_addr
+            .iter()
+            .flat_map(|(a, names)| names.iter().map(move |n| (*a, n.as_str())))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+}
+
+fn invalid_data(line: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid symbol line '{line}'"),
+    )
+}
@@ -0,0 +1,191 @@
+use std::io::{self, Write};
+
+use crate::decode::Op;
+use crate::instructions;
+use crate::source_map::SourceMap;
+use crate::vm::ErrorKind;
+use crate::word::Word;
+
+/// The number of values from the top of the stack shown per instruction.
+const TRACE_DEPTH: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// One aligned, human readable line per instruction.
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+/// Writes a line for every instruction the VM executes.
+///
+/// Each line holds the instruction's address, its mnemonic (`LIT` for literals), its operand
+/// and the top of the stack before and after it ran. The operand is the literal value, the
+/// value `PUSH` pushes, or the address an instruction takes from the stack. Given a source
+/// map, lines also hold the source position of the instruction.
+pub struct Tracer {
+    out: Box<dyn Write>,
+    format: TraceFormat,
+    source_map: Option<SourceMap>,
+}
+
+impl Tracer {
+    pub fn new(out: impl Write + 'static, format: TraceFormat) -> Self {
+        Tracer {
+            out: Box::new(out),
+            format,
+            source_map: None,
+        }
+    }
+
+    pub fn with_source_map(mut self, source_map: SourceMap) -> Self {
+        self.source_map = Some(source_map);
+        self
+    }
+
+    pub fn stderr(format: TraceFormat) -> Self {
+        Tracer::new(io::stderr(), format)
+    }
+
+    pub fn file(path: &str, format: TraceFormat) -> io::Result<Self> {
+        let file = std::fs::File::create(path)?;
+        Ok(Tracer::new(io::BufWriter::new(file), format))
+    }
+
+    /// Captures the state needed to trace `inst`, decoded as `op`, before it is executed.
+    pub(crate) fn begin<W: Word>(&self, ip: usize, op: Op<W>, inst: W, stack: &[W]) -> TraceEntry {
+        let operand = if inst.is_literal() {
+            Some(inst.into())
+        } else if let Op::PushNext(v) = op {
+            Some(v.into())
+        } else if inst.opcode().is_some_and(instructions::takes_address) {
+            stack.last().map(|&v| v.into())
+        } else {
+            None
+        };
+        TraceEntry {
+            ip,
+            mnemonic: op_name(inst),
+            operand,
+            before: top(stack),
+        }
+    }
+
+    /// Writes the line for an executed instruction.
+    pub(crate) fn finish<W: Word>(
+        &mut self,
+        entry: TraceEntry,
+        after: Result<&[W], &ErrorKind>,
+    ) -> io::Result<()> {
+        match self.format {
+            TraceFormat::Text => self.write_text(entry, after),
+            TraceFormat::Json => self.write_json(entry, after),
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    fn write_text<W: Word>(
+        &mut self,
+        entry: TraceEntry,
+        after: Result<&[W], &ErrorKind>,
+    ) -> io::Result<()> {
+        let operand = entry.operand.map(|v| v.to_string()).unwrap_or_default();
+        write!(
+            self.out,
+            "{:>6}  {:<4} {:>11}  [{}] -> ",
+            entry.ip,
+            entry.mnemonic,
+            operand,
+            join(&entry.before, " ")
+        )?;
+        match after {
+            Ok(stack) => write!(self.out, "[{}]", join(&top(stack), " "))?,
+            Err(e) => write!(self.out, "error {e:?}")?,
+        }
+        match self.source_map.as_ref().and_then(|m| m.describe(entry.ip)) {
+            Some(source) => writeln!(self.out, "  ; {source}"),
+            None => writeln!(self.out),
+        }
+    }
+
+    fn write_json<W: Word>(
+        &mut self,
+        entry: TraceEntry,
+        after: Result<&[W], &ErrorKind>,
+    ) -> io::Result<()> {
+        let operand = entry.operand.map_or("null".to_string(), |v| v.to_string());
+        write!(
+            self.out,
+            "{{\"ip\":{},\"op\":\"{}\",\"operand\":{},\"before\":[{}],",
+            entry.ip,
+            entry.mnemonic,
+            operand,
+            join(&entry.before, ",")
+        )?;
+        if let Some(map) = &self.source_map {
+            if let Some(location) = map.get(entry.ip) {
+                write!(
+                    self.out,
+                    "\"file\":{},\"line\":{},\"column\":{},",
+                    json_string(map.file()),
+                    location.line,
+                    location.column
+                )?;
+            }
+        }
+        match after {
+            Ok(stack) => writeln!(self.out, "\"after\":[{}]}}", join(&top(stack), ",")),
+            Err(e) => writeln!(self.out, "\"error\":\"{e:?}\"}}"),
+        }
+    }
+}
+
+pub(crate) struct TraceEntry {
+    ip: usize,
+    mnemonic: &'static str,
+    operand: Option<i64>,
+    before: Vec<i64>,
+}
+
+/// The mnemonic of `inst`, `LIT` for literals and `???` for unknown instructions.
+pub(crate) fn op_name<W: Word>(inst: W) -> &'static str {
+    if inst.is_literal() {
+        "LIT"
+    } else {
+        inst.opcode()
+            .and_then(instructions::mnemonic)
+            .unwrap_or("???")
+    }
+}
+
+fn top<W: Word>(stack: &[W]) -> Vec<i64> {
+    stack[stack.len().saturating_sub(TRACE_DEPTH)..]
+        .iter()
+        .map(|&v| v.into())
+        .collect()
+}
+
+fn join(values: &[i64], sep: &str) -> String {
+    values
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join(sep)
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
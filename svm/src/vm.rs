@@ -1,316 +1,1077 @@
-use log::{error, info};
+#[cfg(feature = "jit")]
+use std::any::Any;
+use std::collections::HashMap;
 
-use svm::instructions;
+use log::info;
+
+use crate::decode::{decode, Op};
+use crate::device::{Bus, Device, OverlapError};
+use crate::instructions::EOF;
+use crate::io::{Io, StdIo};
+#[cfg(feature = "jit")]
+use crate::jit::{Context, Jit};
+use crate::profile::{Profiler, Sample};
+use crate::program::Program;
+use crate::snapshot::Snapshot;
+use crate::trace::{op_name, Tracer};
+use crate::word::Word;
 
 const STACK_SIZE: usize = 1024;
 const MEM_SIZE: usize = 1024;
+const MAX_STACK_SIZE: usize = 1 << 24;
+const CALL_STACK_SIZE: usize = 1024;
+/// The number of values from the top of the stack kept in a [`VMError`].
+const ERROR_STACK_DEPTH: usize = 8;
 
-#[derive(Clone, Copy)]
-pub enum VMError {
+/// What went wrong when a program failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
     StackOverflow,
-    CorruptStack,
+    /// An instruction needed more values than the stack held.
+    StackUnderflow,
+    /// `CALL` with a full return stack.
+    CallStackOverflow,
+    /// `RET` with an empty return stack.
+    CallStackUnderflow,
     InvalidMemoryAddress,
-    UnknownInstruction(i32),
+    /// A jump to the given address, which is outside the program.
+    InvalidJumpTarget(i64),
+    UnknownInstruction(i64),
     IOError,
+    /// `DIV` or `MOD` by zero.
+    DivisionByZero,
+    /// `DIV` of the most negative word by `-1`.
+    ArithmeticOverflow,
+    /// `SYS` with a call number no host function is registered for.
+    UnknownHostFunction(i64),
+    /// The host function with the given call number failed with the given code.
+    HostFunctionFailed(i64, i32),
+}
+
+/// The largest status `EXIT` passes on as the exit code of `svm`.
+///
+/// The codes above it are reserved for `svm` itself, so a program cannot make it look as if
+/// `svm` failed.
+pub const MAX_EXIT_STATUS: u8 = 63;
+
+impl ErrorKind {
+    /// The exit code `svm` fails with, which is the same for all errors of a class and above
+    /// [`MAX_EXIT_STATUS`].
+    pub fn exit_code(self) -> u8 {
+        match self {
+            ErrorKind::StackOverflow | ErrorKind::StackUnderflow => 70,
+            ErrorKind::CallStackOverflow | ErrorKind::CallStackUnderflow => 71,
+            ErrorKind::InvalidMemoryAddress => 72,
+            ErrorKind::InvalidJumpTarget(_) => 73,
+            ErrorKind::UnknownInstruction(_) => 74,
+            ErrorKind::DivisionByZero | ErrorKind::ArithmeticOverflow => 75,
+            ErrorKind::IOError => 76,
+            ErrorKind::UnknownHostFunction(_) | ErrorKind::HostFunctionFailed(..) => 77,
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            ErrorKind::StackOverflow => write!(f, "stack overflow"),
+            ErrorKind::StackUnderflow => write!(f, "stack underflow"),
+            ErrorKind::CallStackOverflow => write!(f, "call stack overflow"),
+            ErrorKind::CallStackUnderflow => write!(f, "return without call"),
+            ErrorKind::InvalidMemoryAddress => write!(f, "invalid memory address"),
+            ErrorKind::InvalidJumpTarget(addr) => write!(f, "invalid jump target {addr}"),
+            // in 32 bits unless the word needs 64
+            ErrorKind::UnknownInstruction(inst) => match i32::try_from(inst) {
+                Ok(inst) => write!(f, "unknown instruction {inst:#X}"),
+                Err(_) => write!(f, "unknown instruction {inst:#X}"),
+            },
+            ErrorKind::IOError => write!(f, "io error"),
+            ErrorKind::DivisionByZero => write!(f, "division by zero"),
+            ErrorKind::ArithmeticOverflow => write!(f, "arithmetic overflow"),
+            ErrorKind::UnknownHostFunction(number) => write!(f, "unknown host function {number}"),
+            ErrorKind::HostFunctionFailed(number, code) => {
+                write!(f, "host function {number} failed with code {code}")
+            }
+        }
+    }
+}
+
+/// A failed program, with the state of the VM where it failed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VMError {
+    pub kind: ErrorKind,
+    /// The address of the instruction that failed.
+    pub ip: usize,
+    /// The instruction that failed, or `None` if the VM failed outside the program, e.g.
+    /// flushing the output after it ended.
+    pub opcode: Option<i64>,
+    /// The number of values on the stack after the failure.
+    pub stack_depth: usize,
+    /// Up to the top eight values on the stack after the failure, bottom first.
+    pub stack_top: Vec<i64>,
+}
+
+impl std::fmt::Display for VMError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} at {}", self.kind, self.ip)?;
+        if let Some(opcode) = self.opcode {
+            write!(f, " ({})", op_name(opcode))?;
+        }
+        write!(f, ", stack depth {}", self.stack_depth)?;
+        if !self.stack_top.is_empty() {
+            let top: Vec<String> = self.stack_top.iter().map(i64::to_string).collect();
+            let more = if self.stack_depth > self.stack_top.len() {
+                "... "
+            } else {
+                ""
+            };
+            write!(f, " [{more}{}]", top.join(" "))?;
+        }
+        Ok(())
+    }
 }
 
-pub struct VM {
-    stack: Box<[i32]>,
-    memory: Box<[i32]>,
-    program: Vec<i32>,
+impl std::error::Error for VMError {}
+
+/// Why a program stopped running without an error.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitReason {
+    /// The program executed `HALT`.
+    Halted,
+    /// The program executed `EXIT` with the given status.
+    Exited(i64),
+    /// The instruction pointer ran past the last instruction.
+    EndOfProgram,
+    /// The instruction budget ran out before the program stopped. Execution can be resumed.
+    Yielded,
+}
+
+impl ExitReason {
+    /// The exit code `svm` stops with, or `None` if the program has not stopped.
+    ///
+    /// That is 0 once it halts or ends, and the status passed to `EXIT` otherwise. Statuses
+    /// outside 0..=[`MAX_EXIT_STATUS`] become `MAX_EXIT_STATUS`, so they never look like
+    /// success or like a failure of `svm`.
+    pub fn exit_code(self) -> Option<u8> {
+        match self {
+            ExitReason::Halted | ExitReason::EndOfProgram => Some(0),
+            ExitReason::Exited(status) => Some(
+                u8::try_from(status)
+                    .ok()
+                    .filter(|&code| code <= MAX_EXIT_STATUS)
+                    .unwrap_or(MAX_EXIT_STATUS),
+            ),
+            ExitReason::Yielded => None,
+        }
+    }
+}
+
+/// Sizes a [`VM`] is created with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VMConfig {
+    /// The number of stack cells, or the initial number if the stack is growable.
+    pub stack_size: usize,
+    /// The number of data memory cells.
+    pub memory_size: usize,
+    /// Lets the stack grow on overflow instead of failing.
+    pub growable_stack: bool,
+    /// The number of cells a growable stack may grow to.
+    pub max_stack_size: usize,
+    /// The number of return addresses `CALL` may nest.
+    pub call_stack_size: usize,
+    /// The number of instructions to execute before yielding, or `None` for no limit.
+    pub max_steps: Option<u64>,
+    /// Accepts program files written before the current format, including plain code.
+    pub legacy_format: bool,
+}
+
+impl VMConfig {
+    pub fn new() -> Self {
+        VMConfig {
+            stack_size: STACK_SIZE,
+            memory_size: MEM_SIZE,
+            growable_stack: false,
+            max_stack_size: MAX_STACK_SIZE,
+            call_stack_size: CALL_STACK_SIZE,
+            max_steps: None,
+            legacy_format: false,
+        }
+    }
+
+    pub fn stack_size(mut self, stack_size: usize) -> Self {
+        self.stack_size = stack_size;
+        self
+    }
+
+    pub fn memory_size(mut self, memory_size: usize) -> Self {
+        self.memory_size = memory_size;
+        self
+    }
+
+    pub fn growable_stack(mut self, growable_stack: bool) -> Self {
+        self.growable_stack = growable_stack;
+        self
+    }
+
+    pub fn max_stack_size(mut self, max_stack_size: usize) -> Self {
+        self.max_stack_size = max_stack_size;
+        self
+    }
+
+    pub fn call_stack_size(mut self, call_stack_size: usize) -> Self {
+        self.call_stack_size = call_stack_size;
+        self
+    }
+
+    pub fn max_steps(mut self, max_steps: Option<u64>) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    pub fn legacy_format(mut self, legacy_format: bool) -> Self {
+        self.legacy_format = legacy_format;
+        self
+    }
+}
+
+impl Default for VMConfig {
+    fn default() -> Self {
+        VMConfig::new()
+    }
+}
+
+/// The signature of host functions: the arguments, the results to fill in and the memory.
+type HostFn<W> = dyn FnMut(&[W], &mut [W], &mut [W]) -> Result<(), i32>;
+
+/// A function registered with [`VM::register_host_function`].
+struct HostFunction<W> {
+    args: usize,
+    results: usize,
+    f: Box<HostFn<W>>,
+}
+
+/// Executes programs whose values are words of type `W`, 32-bit unless chosen otherwise.
+pub struct VM<I: Io = StdIo, W: Word = i32> {
+    io: I,
+    config: VMConfig,
+    stack: Box<[W]>,
+    memory: Box<[W]>,
+    /// Devices mapped over memory addresses.
+    bus: Bus,
+    program: Vec<W>,
+    /// `program` decoded for execution, one op per word.
+    code: Vec<Op<W>>,
+    call_stack: Vec<usize>,
+    fuel: Option<u64>,
+    tracer: Option<Tracer>,
+    profiler: Option<Profiler>,
+    host_functions: HashMap<i64, HostFunction<W>>,
+    /// Native code compiled from hot parts of `code`, if enabled.
+    #[cfg(feature = "jit")]
+    jit: Option<Jit>,
     ip: usize,
     sp: usize,
     hf: bool,
     rf: bool,
+    /// The status passed to `EXIT`, if the program stopped that way.
+    exit_status: Option<i64>,
 }
 
 impl VM {
     pub fn new() -> Self {
+        VM::with_config(VMConfig::default())
+    }
+
+    pub fn with_config(config: VMConfig) -> Self {
+        VM::with_config_and_io(config, StdIo::new())
+    }
+
+    /// Creates a VM ready to execute `program`.
+    pub fn with_program(program: Vec<i32>) -> Self {
+        let mut vm = VM::new();
+        vm.load_program(Program::new(program));
+        vm
+    }
+
+    /// Creates a VM from an assembled program held in memory.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, std::io::Error> {
+        let mut vm = VM::new();
+        vm.load_bytes(bytes)?;
+        Ok(vm)
+    }
+}
+
+impl<I: Io> VM<I> {
+    /// Creates a VM whose `IN` and `OUT` instructions go through `io`.
+    pub fn with_io(io: I) -> Self {
+        VM::with_config_and_io(VMConfig::default(), io)
+    }
+}
+
+impl<I: Io, W: Word> VM<I, W> {
+    /// Creates a VM with the given sizes whose `IN` and `OUT` instructions go through `io`.
+    ///
+    /// This is also how to create a VM with another word size, e.g.
+    /// `VM::<_, i64>::with_config_and_io(config, io)`.
+    pub fn with_config_and_io(config: VMConfig, io: I) -> Self {
         VM {
-            stack: vec![0; STACK_SIZE].into_boxed_slice(),
-            memory: vec![0; MEM_SIZE].into_boxed_slice(),
+            io,
+            config,
+            stack: vec![W::default(); config.stack_size].into_boxed_slice(),
+            memory: vec![W::default(); config.memory_size].into_boxed_slice(),
+            bus: Bus::default(),
             program: Vec::new(),
+            code: Vec::new(),
+            call_stack: Vec::new(),
+            fuel: config.max_steps,
+            tracer: None,
+            profiler: None,
+            host_functions: HashMap::new(),
+            #[cfg(feature = "jit")]
+            jit: None,
             ip: 0,
             sp: 0,
             hf: false,
             rf: false,
+            exit_status: None,
         }
     }
 
     pub fn load(&mut self, filename: &str) -> Result<(), std::io::Error> {
-        use std::io::Read;
         info!("loading program from file [{filename}]");
-        let mut file = std::fs::File::open(filename)?;
-        let mut bytes = Vec::with_capacity(4);
-        loop {
-            bytes.clear();
-            let read = file.by_ref().take(4).read_to_end(&mut bytes)?;
-            if read == 0 {
-                break;
-            }
-            let instruction = i32::from_le_bytes(bytes[0..4].try_into().unwrap());
-            self.program.push(instruction);
-        }
+        let bytes = std::fs::read(filename)?;
+        self.load_bytes(&bytes)?;
         info!("program loaded into memory");
         Ok(())
     }
 
-    pub fn run(&mut self) {
-        info!("starting program execution");
-        while self.ip < self.program.len() && !self.hf {
-            match self.tick() {
-                Ok(_) => {}
-                Err(VMError::CorruptStack) => {
-                    error!("corrupt stack");
-                    return;
-                }
-                Err(VMError::InvalidMemoryAddress) => {
-                    error!("invalid memory address");
-                    return;
-                }
-                Err(VMError::StackOverflow) => {
-                    error!("stack overflow");
-                    return;
-                }
-                Err(VMError::UnknownInstruction(inst)) => {
-                    error!("unknown instruction {inst:#X}");
-                    return;
-                }
-                Err(VMError::IOError) => {
-                    error!("io error");
-                    return;
-                }
+    /// Appends an assembled program to the loaded code. See [`Program`] for the format.
+    pub fn load_bytes(&mut self, bytes: &[u8]) -> Result<(), std::io::Error> {
+        let program = if self.config.legacy_format {
+            Program::from_legacy_bytes(bytes)?
+        } else {
+            Program::from_bytes(bytes)?
+        };
+        self.load_program(program);
+        Ok(())
+    }
+
+    /// Appends `program` to the loaded code.
+    ///
+    /// The stack and memory are grown to the sizes the program declares, if they are larger
+    /// than the current ones, and the program's data is copied to the start of memory.
+    pub fn load_program(&mut self, program: Program<W>) {
+        if let Some(size) = program.stack_size {
+            if size > self.stack.len() {
+                resize(&mut self.stack, size);
             }
         }
+        if let Some(size) = program.memory_size {
+            if size > self.memory.len() {
+                resize(&mut self.memory, size);
+            }
+        }
+        if program.data.len() > self.memory.len() {
+            resize(&mut self.memory, program.data.len());
+        }
+        self.memory[..program.data.len()].copy_from_slice(&program.data);
+        self.program.extend(program.code);
+        self.code = decode(&self.program);
+        self.reset_jit();
+    }
 
-        info!("completed program execution");
+    /// Runs the program until it halts, runs off the end, runs out of fuel, or fails.
+    pub fn run(&mut self) -> Result<ExitReason, VMError> {
+        info!("starting program execution");
+        let reason = self.run_steps(u64::MAX)?;
+        if reason != ExitReason::Yielded {
+            info!("completed program execution");
+        }
+        Ok(reason)
     }
 
-    fn tick(&mut self) -> Result<(), VMError> {
-        let inst = self.program[self.ip];
-        self.execute(inst)?;
-        self.ip += 1;
-        Ok(())
+    /// Runs at most `steps` instructions, returning [`ExitReason::Yielded`] if the program
+    /// is still running afterwards. Calling it again resumes where it left off.
+    pub fn run_for(&mut self, steps: u64) -> Result<ExitReason, VMError> {
+        self.run_steps(steps)
     }
 
-    fn execute(&mut self, inst: i32) -> Result<(), VMError> {
-        // non negative values are pushed to the stack
-        if inst >= 0 {
-            self.push(inst)?;
+    /// Runs at most `steps` instructions, flushing the output once it stops.
+    fn run_steps(&mut self, steps: u64) -> Result<ExitReason, VMError> {
+        let result = if self.tracer.is_some() || self.profiler.is_some() {
+            self.run_stepped(steps)
         } else {
-            use instructions::*;
-            match inst {
-                // I/O
-                IN => {
-                    use std::io::Write;
-                    if self.rf {
-                        unimplemented!("IN raw mode");
-                    } else {
-                        print!("?");
-                        std::io::stdout().flush().map_err(|_| VMError::IOError)?;
-
-                        let mut line = String::new();
-                        std::io::stdin()
-                            .read_line(&mut line)
-                            .map_err(|_| VMError::IOError)?;
-
-                        let v = line.trim().parse::<i32>().map_err(|_| VMError::IOError)?;
-                        self.push(v)?;
-                    }
-                }
-                OUT => {
-                    if !self.rf {
-                        let v = self.pop()?;
-                        println!("{v}");
-                    } else {
-                        let c = (self.pop()? % 256) as u8 as char;
-                        print!("{c}");
+            let budget = steps.min(self.fuel.unwrap_or(u64::MAX));
+            let (executed, result) = self.dispatch(budget);
+            if let Some(fuel) = &mut self.fuel {
+                *fuel -= executed;
+            }
+            result.map(|_| self.exit_reason().unwrap_or(ExitReason::Yielded))
+        };
+        let mut flushed = self.io.flush().is_ok();
+        if let Some(tracer) = &mut self.tracer {
+            flushed &= tracer.flush().is_ok();
+        }
+        let reason = result?;
+        if !flushed {
+            return Err(self.error_at(ErrorKind::IOError, self.ip));
+        }
+        Ok(reason)
+    }
+
+    /// Runs one instruction at a time, for tracing and profiling.
+    fn run_stepped(&mut self, steps: u64) -> Result<ExitReason, VMError> {
+        for _ in 0..steps {
+            if let Some(reason) = self.step()? {
+                return Ok(reason);
+            }
+        }
+        Ok(self.exit_reason().unwrap_or(ExitReason::Yielded))
+    }
+
+    /// Executes at most `budget` instructions from the pre-decoded code, returning how many
+    /// were executed.
+    fn dispatch(&mut self, budget: u64) -> (u64, Result<(), VMError>) {
+        let mut executed = 0;
+        while !self.hf && executed < budget {
+            #[cfg(feature = "jit")]
+            if let Some(n) = self.run_compiled(budget - executed) {
+                executed += n;
+                continue;
+            }
+            let Some(&op) = self.code.get(self.ip) else {
+                break;
+            };
+            // a fused op counts as both of its instructions
+            let (op, count) = if !op.is_fused() {
+                (op, 1)
+            } else if budget - executed >= 2 {
+                (op, 2)
+            } else {
+                (Op::decode(self.program[self.ip]), 1)
+            };
+            match self.execute(op) {
+                Ok(next) => self.ip = next,
+                Err(kind) => {
+                    // past the check for room for its literal, a fused op fails in its second
+                    // instruction, which is also where stepping through it stops
+                    if op.is_fused() && kind != ErrorKind::StackOverflow {
+                        self.ip += 1;
+                        executed += 1;
                     }
+                    return (executed, Err(self.error_at(kind, self.ip)));
                 }
+            }
+            executed += count;
+        }
+        (executed, Ok(()))
+    }
 
-                // Arithmetic
-                ADD => {
-                    let a = self.pop()?;
-                    let b = self.pop()?;
-                    self.push(b + a)?;
-                }
-                SUB => {
-                    let a = self.pop()?;
-                    let b = self.pop()?;
-                    self.push(b - a)?;
-                }
-                MUL => {
-                    let a = self.pop()?;
-                    let b = self.pop()?;
-                    self.push(b * a)?;
-                }
-                DIV => {
-                    let a = self.pop()?;
-                    let b = self.pop()?;
-                    self.push(b / a)?;
-                }
-                MOD => {
-                    let a = self.pop()?;
-                    let b = self.pop()?;
-                    self.push(b % a)?;
-                }
-                NEG => {
-                    let a = self.pop()?;
-                    self.push(-a)?;
-                }
-                INC => {
-                    let a = self.pop()?;
-                    self.push(a + 1)?;
-                }
-                DEC => {
-                    let a = self.pop()?;
-                    self.push(a - 1)?;
-                }
+    /// Runs the compiled block at `ip`, if there is one that fits in `budget`, returning how
+    /// many instructions it executed. Returns `None` if the interpreter should execute the
+    /// next instruction instead.
+    #[cfg(feature = "jit")]
+    fn run_compiled(&mut self, budget: u64) -> Option<u64> {
+        // compiled code works on 32-bit words
+        let code = (&self.code as &dyn Any).downcast_ref::<Vec<Op<i32>>>()?;
+        // devices are left to the interpreter
+        let memory_len = self.memory.len().min(self.bus.first_address());
+        let block = self.jit.as_mut()?.block_at(self.ip, code, memory_len)?;
+        if block.max_executed > budget {
+            return None;
+        }
+        let stack = (&mut self.stack as &mut dyn Any).downcast_mut::<Box<[i32]>>()?;
+        let (stack, stack_len) = (stack.as_mut_ptr(), stack.len());
+        let memory = (&mut self.memory as &mut dyn Any).downcast_mut::<Box<[i32]>>()?;
+        let mut ctx = Context {
+            stack,
+            sp: self.sp as u64,
+            stack_len: stack_len as u64,
+            memory: memory.as_mut_ptr(),
+            memory_len: memory_len as u64,
+            ip: self.ip as u64,
+            executed: 0,
+        };
+        // SAFETY: the context describes the live stack and memory, which the block does not
+        // outlive.
+        unsafe { block.run(&mut ctx) };
+        self.sp = ctx.sp as usize;
+        self.ip = ctx.ip as usize;
+        (ctx.executed > 0).then_some(ctx.executed)
+    }
 
-                // Bitwise operations
-                AND => {
-                    let a = self.pop()?;
-                    let b = self.pop()?;
-                    self.push(b & a)?;
-                }
-                OR => {
-                    let a = self.pop()?;
-                    let b = self.pop()?;
-                    self.push(b | a)?;
-                }
-                XOR => {
-                    let a = self.pop()?;
-                    let b = self.pop()?;
-                    self.push(b ^ a)?;
-                }
-                NOT => {
-                    let a = self.pop()?;
-                    self.push(!a)?;
-                }
-                SHR => {
-                    let a = self.pop()?;
-                    let b = self.pop()?;
-                    self.push(b >> a)?;
-                }
-                SHL => {
-                    let a = self.pop()?;
-                    let b = self.pop()?;
-                    self.push(b << a)?;
-                }
+    /// Executes a single instruction.
+    ///
+    /// Returns `Some` once the program has stopped, in which case further calls do nothing, or
+    /// if it is out of fuel.
+    pub fn step(&mut self) -> Result<Option<ExitReason>, VMError> {
+        if let Some(reason) = self.exit_reason() {
+            return Ok(Some(reason));
+        }
+        if self.fuel == Some(0) {
+            return Ok(Some(ExitReason::Yielded));
+        }
+        let ip = self.ip;
+        self.tick().map_err(|kind| self.error_at(kind, ip))?;
+        if let Some(fuel) = &mut self.fuel {
+            *fuel -= 1;
+        }
+        Ok(self.exit_reason())
+    }
 
-                // Stack
-                POP => {
-                    self.pop()?;
-                }
-                DUP => {
-                    self.assert_stack_free_space(1)?;
-                    self.assert_stack_size(1)?;
-                    let v = self.stack[self.sp - 1];
-                    self.stack[self.sp] = v;
-                    self.sp += 1;
-                }
-                SWP => {
-                    let a = self.pop()?;
-                    let b = self.pop()?;
-                    self.push(a)?;
-                    self.push(b)?;
-                }
-                OVR => {
-                    self.assert_stack_size(2)?;
-                    let v = self.stack[self.sp - 2];
-                    self.push(v)?;
-                }
+    /// The number of instructions left before execution yields, if limited.
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    /// Sets the instruction budget, or removes it with `None`.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+
+    /// Adds to a limited instruction budget so a yielded program can continue.
+    pub fn add_fuel(&mut self, fuel: u64) {
+        if let Some(f) = &mut self.fuel {
+            *f = f.saturating_add(fuel);
+        }
+    }
+
+    fn exit_reason(&self) -> Option<ExitReason> {
+        if self.hf {
+            Some(
+                self.exit_status
+                    .map_or(ExitReason::Halted, ExitReason::Exited),
+            )
+        } else if self.ip >= self.program.len() {
+            Some(ExitReason::EndOfProgram)
+        } else {
+            None
+        }
+    }
+
+    /// The live portion of the stack, bottom first.
+    pub fn stack(&self) -> &[W] {
+        &self.stack[..self.sp]
+    }
+
+    /// The stack pointer, i.e. the number of values on the stack.
+    pub fn sp(&self) -> usize {
+        self.sp
+    }
+
+    /// The address of the next instruction to execute.
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    /// The live portion of the stack, for tools that modify a paused program.
+    pub fn stack_mut(&mut self) -> &mut [W] {
+        &mut self.stack[..self.sp]
+    }
+
+    /// Moves execution to `ip`, for tools that modify a paused program.
+    pub fn set_ip(&mut self, ip: usize) {
+        self.ip = ip;
+    }
 
-                // Memory
-                LOAD => {
-                    let addr = self.pop()? as usize;
-                    self.assert_memory_address(addr)?;
-                    let v = self.memory[addr];
-                    self.push(v)?;
+    /// Maps `device` at the addresses from `start` on, taking precedence over memory. Mapped
+    /// addresses need not be inside memory.
+    pub fn map_device(
+        &mut self,
+        start: usize,
+        device: impl Device + 'static,
+    ) -> Result<(), OverlapError> {
+        self.bus.map(start, Box::new(device))?;
+        self.reset_jit();
+        Ok(())
+    }
+
+    /// The data memory, not including mapped devices.
+    pub fn memory(&self) -> &[W] {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut [W] {
+        &mut self.memory
+    }
+
+    pub fn program(&self) -> &[W] {
+        &self.program
+    }
+
+    /// The return addresses of the active `CALL`s, outermost first.
+    pub fn call_stack(&self) -> &[usize] {
+        &self.call_stack
+    }
+
+    pub fn io(&self) -> &I {
+        &self.io
+    }
+
+    pub fn io_mut(&mut self) -> &mut I {
+        &mut self.io
+    }
+
+    pub fn into_io(self) -> I {
+        self.io
+    }
+
+    pub fn config(&self) -> &VMConfig {
+        &self.config
+    }
+
+    /// Captures the complete state of the VM apart from its I/O backend and tracer.
+    pub fn snapshot(&self) -> Snapshot<W> {
+        Snapshot {
+            config: self.config,
+            program: self.program.clone(),
+            stack: self.stack().to_vec(),
+            stack_size: self.stack.len(),
+            memory: self.memory.to_vec(),
+            call_stack: self.call_stack.clone(),
+            fuel: self.fuel,
+            ip: self.ip,
+            hf: self.hf,
+            rf: self.rf,
+            exit_status: self.exit_status,
+        }
+    }
+
+    /// Replaces the state of the VM with `snapshot`, keeping its I/O backend and tracer.
+    pub fn restore(&mut self, snapshot: Snapshot<W>) {
+        let mut stack = snapshot.stack;
+        self.sp = stack.len();
+        stack.resize(snapshot.stack_size.max(self.sp), W::default());
+
+        self.config = snapshot.config;
+        self.program = snapshot.program;
+        self.code = decode(&self.program);
+        self.stack = stack.into_boxed_slice();
+        self.memory = snapshot.memory.into_boxed_slice();
+        self.reset_jit();
+        self.call_stack = snapshot.call_stack;
+        self.fuel = snapshot.fuel;
+        self.ip = snapshot.ip;
+        self.hf = snapshot.hf;
+        self.rf = snapshot.rf;
+        self.exit_status = snapshot.exit_status;
+    }
+
+    /// Compiles frequently executed code to native code. Has no effect while tracing or
+    /// profiling, or with words other than `i32`.
+    #[cfg(feature = "jit")]
+    pub fn enable_jit(&mut self) {
+        if W::BITS == 32 && self.jit.is_none() {
+            self.jit = Some(Jit::new(self.code.len()));
+        }
+    }
+
+    #[cfg(feature = "jit")]
+    pub fn disable_jit(&mut self) {
+        self.jit = None;
+    }
+
+    /// Discards compiled code after the program or memory changed.
+    fn reset_jit(&mut self) {
+        #[cfg(feature = "jit")]
+        if self.jit.is_some() {
+            self.jit = Some(Jit::new(self.code.len()));
+        }
+    }
+
+    /// Traces every executed instruction with `tracer`, or stops tracing with `None`.
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
+    }
+
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+
+    /// Makes `f` available to programs as `SYS` call `number`, replacing any function
+    /// previously registered under it.
+    ///
+    /// `SYS` pops the call number, then takes `args` values off the stack and passes them to
+    /// `f` in the order they were pushed, together with `results` cells to fill in and the
+    /// VM's memory. The results are pushed in order once `f` returns. An error code returned by
+    /// `f` stops the program with [`ErrorKind::HostFunctionFailed`].
+    pub fn register_host_function<F>(&mut self, number: i32, args: usize, results: usize, f: F)
+    where
+        F: FnMut(&[W], &mut [W], &mut [W]) -> Result<(), i32> + 'static,
+    {
+        let f = Box::new(f);
+        self.host_functions
+            .insert(number.into(), HostFunction { args, results, f });
+    }
+
+    /// Profiles every executed instruction with `profiler`, or stops profiling with `None`.
+    pub fn set_profiler(&mut self, profiler: Option<Profiler>) {
+        self.profiler = profiler;
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    pub fn take_profiler(&mut self) -> Option<Profiler> {
+        self.profiler.take()
+    }
+
+    /// The halt flag, set by `HALT`.
+    pub fn hf(&self) -> bool {
+        self.hf
+    }
+
+    /// The raw mode flag, toggled by `RF` and `CRF`.
+    pub fn rf(&self) -> bool {
+        self.rf
+    }
+
+    /// Executes the instruction at `ip` without fusing it with the next one.
+    fn tick(&mut self) -> Result<(), ErrorKind> {
+        let inst = self.program[self.ip];
+        let op = match self.code[self.ip] {
+            op if op.is_fused() => Op::decode(inst),
+            op => op,
+        };
+        if self.tracer.is_none() && self.profiler.is_none() {
+            self.ip = self.execute(op)?;
+            return Ok(());
+        }
+        let sample = self
+            .profiler
+            .as_ref()
+            .map(|p| p.begin(self.ip, inst, self.stack(), self.memory.len()));
+        let Some(mut tracer) = self.tracer.take() else {
+            self.ip = self.execute(op)?;
+            self.profile(sample);
+            return Ok(());
+        };
+        let entry = tracer.begin(self.ip, op, inst, self.stack());
+        let result = self.execute(op).map(|next| self.ip = next);
+        let traced = tracer.finish(entry, result.as_ref().map(|_| self.stack()));
+        self.tracer = Some(tracer);
+        result?;
+        self.profile(sample);
+        traced.map_err(|_| ErrorKind::IOError)
+    }
+
+    /// Describes a failure of the instruction at `ip` with the current state of the VM.
+    fn error_at(&self, kind: ErrorKind, ip: usize) -> VMError {
+        let stack = self.stack();
+        VMError {
+            kind,
+            ip,
+            opcode: self.program.get(ip).map(|&inst| inst.into()),
+            stack_depth: stack.len(),
+            stack_top: stack[stack.len().saturating_sub(ERROR_STACK_DEPTH)..]
+                .iter()
+                .map(|&v| v.into())
+                .collect(),
+        }
+    }
+
+    fn profile(&mut self, sample: Option<Sample>) {
+        if let (Some(profiler), Some(sample)) = (&mut self.profiler, sample) {
+            profiler.finish(sample, self.sp, self.ip);
+        }
+    }
+
+    /// Executes `op`, returning the address to continue at.
+    #[inline(always)]
+    fn execute(&mut self, op: Op<W>) -> Result<usize, ErrorKind> {
+        let next = self.ip + 1;
+        match op {
+            Op::Push(v) => self.push(v)?,
+            Op::PushNext(v) => {
+                self.push(v)?;
+                return Ok(next + 1);
+            }
+
+            // I/O
+            Op::In => {
+                if self.rf {
+                    let c = self.io.read_char().map_err(|_| ErrorKind::IOError)?;
+                    self.push(W::from(c.map_or(EOF, |c| c as i32)))?;
+                } else {
+                    let v = self.io.read_number().map_err(|_| ErrorKind::IOError)?;
+                    self.push(W::try_from(v).map_err(|_| ErrorKind::IOError)?)?;
                 }
-                STOR => {
-                    let addr = self.pop()? as usize;
+            }
+            Op::Out => {
+                if !self.rf {
                     let v = self.pop()?;
-                    self.assert_memory_address(addr)?;
-                    self.memory[addr] = v;
+                    self.io
+                        .write_number(v.into())
+                        .map_err(|_| ErrorKind::IOError)?;
+                } else {
+                    let c = u32::try_from(Into::<i64>::into(self.pop()?))
+                        .ok()
+                        .and_then(char::from_u32)
+                        .unwrap_or(char::REPLACEMENT_CHARACTER);
+                    self.io.write_char(c).map_err(|_| ErrorKind::IOError)?;
                 }
+            }
 
-                // Jumps
-                JMP => {
-                    let addr = self.pop()? as usize;
-                    self.assert_memory_address(addr)?;
-                    self.ip = addr - 1;
-                }
-                JE => {
-                    let addr = self.pop()? as usize;
-                    self.assert_memory_address(addr)?;
-                    if self.pop()? == self.pop()? {
-                        self.ip = addr - 1;
-                    }
+            // Arithmetic wraps on overflow
+            Op::Add => self.binary(|b, a| Ok(b.wrapping_add(a)))?,
+            Op::Sub => self.binary(|b, a| Ok(b.wrapping_sub(a)))?,
+            Op::Mul => self.binary(|b, a| Ok(b.wrapping_mul(a)))?,
+            Op::Div => self.binary(|b, a| {
+                if a == W::default() {
+                    return Err(ErrorKind::DivisionByZero);
                 }
-                JNE => {
-                    let addr = self.pop()? as usize;
-                    self.assert_memory_address(addr)?;
-                    if self.pop()? != self.pop()? {
-                        self.ip = addr - 1;
-                    }
-                }
-                JG => {
-                    let addr = self.pop()? as usize;
-                    self.assert_memory_address(addr)?;
-                    if self.pop()? > self.pop()? {
-                        self.ip = addr - 1;
-                    }
-                }
-                JGE => {
-                    let addr = self.pop()? as usize;
-                    self.assert_memory_address(addr)?;
-                    if self.pop()? >= self.pop()? {
-                        self.ip = addr - 1;
-                    }
-                }
-                JL => {
-                    let addr = self.pop()? as usize;
-                    self.assert_memory_address(addr)?;
-                    if self.pop()? < self.pop()? {
-                        self.ip = addr - 1;
-                    }
-                }
-                JLE => {
-                    let addr = self.pop()? as usize;
-                    self.assert_memory_address(addr)?;
-                    if self.pop()? <= self.pop()? {
-                        self.ip = addr - 1;
-                    }
+                b.checked_div(a).ok_or(ErrorKind::ArithmeticOverflow)
+            })?,
+            Op::Mod => self.binary(|b, a| {
+                if a == W::default() {
+                    return Err(ErrorKind::DivisionByZero);
                 }
+                Ok(b.wrapping_rem(a))
+            })?,
+            Op::Neg => self.unary(|a| a.wrapping_neg())?,
+            Op::Inc => self.unary(|a| a.wrapping_add(W::from(1)))?,
+            Op::Dec => self.unary(|a| a.wrapping_sub(W::from(1)))?,
 
-                // Flags
-                RF => {
-                    self.rf = true;
-                }
-                CRF => {
-                    self.rf = false;
+            // Bitwise operations, shift amounts are taken modulo the word size
+            Op::And => self.binary(|b, a| Ok(b & a))?,
+            Op::Or => self.binary(|b, a| Ok(b | a))?,
+            Op::Xor => self.binary(|b, a| Ok(b ^ a))?,
+            Op::Not => self.unary(|a| !a)?,
+            Op::Shr => self.binary(|b, a| Ok(b.wrapping_shr(Into::<i64>::into(a) as u32)))?,
+            Op::Shl => self.binary(|b, a| Ok(b.wrapping_shl(Into::<i64>::into(a) as u32)))?,
+
+            // Floating point, on the bits of words
+            Op::FAdd => self.float_binary(|b, a| b + a)?,
+            Op::FSub => self.float_binary(|b, a| b - a)?,
+            Op::FMul => self.float_binary(|b, a| b * a)?,
+            Op::FDiv => self.float_binary(|b, a| b / a)?,
+            Op::FNeg => self.unary(|a| W::from_float(-a.to_float()))?,
+            Op::FEq => self.binary(|b, a| Ok(W::from((b.to_float() == a.to_float()) as i32)))?,
+            Op::FLt => self.binary(|b, a| Ok(W::from((b.to_float() < a.to_float()) as i32)))?,
+            Op::FLe => self.binary(|b, a| Ok(W::from((b.to_float() <= a.to_float()) as i32)))?,
+            Op::IToF => self.unary(|a| W::from_float(a.int_to_float()))?,
+            Op::FToI => self.unary(|a| W::float_to_int(a.to_float()))?,
+            Op::FIn => {
+                let text = self.io.read_float().map_err(|_| ErrorKind::IOError)?;
+                let v = text.trim().parse().map_err(|_| ErrorKind::IOError)?;
+                self.push(W::from_float(v))?;
+            }
+            Op::FOut => {
+                let v = self.pop()?.to_float();
+                self.io
+                    .write_float(&v.to_string())
+                    .map_err(|_| ErrorKind::IOError)?;
+            }
+
+            // Stack
+            Op::Pop => {
+                self.pop()?;
+            }
+            Op::Dup => {
+                self.assert_stack_free_space(1)?;
+                self.assert_stack_size(1)?;
+                let v = self.stack[self.sp - 1];
+                self.stack[self.sp] = v;
+                self.sp += 1;
+            }
+            Op::Swp => {
+                self.assert_stack_size(2)?;
+                self.stack.swap(self.sp - 1, self.sp - 2);
+            }
+            Op::Ovr => {
+                self.assert_stack_size(2)?;
+                let v = self.stack[self.sp - 2];
+                self.push(v)?;
+            }
+
+            // Memory
+            Op::Load => {
+                let addr = self.pop()?;
+                let v = self.read_memory(addr)?;
+                self.push(v)?;
+            }
+            Op::Stor => {
+                let addr = self.pop()?;
+                let v = self.pop()?;
+                self.write_memory(addr, v)?;
+            }
+
+            // Jumps
+            Op::Jmp => {
+                let addr = self.pop()?;
+                self.assert_jump_target(addr)?;
+                return Ok(address(addr));
+            }
+            Op::Jump(cond) => {
+                let addr = self.pop()?;
+                self.assert_jump_target(addr)?;
+                if cond.test(self.pop()?, self.pop()?) {
+                    return Ok(address(addr));
                 }
+            }
+
+            // Subroutines
+            Op::Call => {
+                let addr = self.pop()?;
+                self.assert_jump_target(addr)?;
+                return self.call(address(addr), next);
+            }
+            Op::Ret => {
+                return self.call_stack.pop().ok_or(ErrorKind::CallStackUnderflow);
+            }
+
+            // Host
+            Op::Sys => {
+                let number = self.pop()?;
+                self.call_host_function(number.into())?;
+            }
+
+            // Flags
+            Op::Rf => {
+                self.rf = true;
+            }
+            Op::Crf => {
+                self.rf = false;
+            }
+
+            // Other
+            Op::Halt => {
+                self.hf = true;
+            }
+            Op::Exit => {
+                self.exit_status = Some(self.pop()?.into());
+                self.hf = true;
+            }
+            Op::Nop => {}
+            Op::Unknown(unk) => return Err(ErrorKind::UnknownInstruction(unk)),
 
-                // Other
-                HALT => {
-                    self.hf = true;
+            // Fused ops check for room for the literal they skip pushing
+            Op::JmpTo(addr) => {
+                self.assert_stack_free_space(1)?;
+                return Ok(addr);
+            }
+            Op::JumpTo(cond, addr) => {
+                self.assert_stack_free_space(1)?;
+                if cond.test(self.pop()?, self.pop()?) {
+                    return Ok(addr);
                 }
-                NOP => {}
-                unk => return Err(VMError::UnknownInstruction(unk)),
+                return Ok(next + 1);
+            }
+            Op::CallTo(addr) => {
+                self.assert_stack_free_space(1)?;
+                return self.call(addr, next + 1);
+            }
+            Op::LoadFrom(addr) => {
+                self.assert_stack_free_space(1)?;
+                let v = self.read_memory(addr)?;
+                self.push(v)?;
+                return Ok(next + 1);
             }
+            Op::StorTo(addr) => {
+                self.assert_stack_free_space(1)?;
+                let v = self.pop()?;
+                self.write_memory(addr, v)?;
+                return Ok(next + 1);
+            }
+        }
+        Ok(next)
+    }
+
+    fn call(&mut self, addr: usize, ret: usize) -> Result<usize, ErrorKind> {
+        if self.call_stack.len() >= self.config.call_stack_size {
+            return Err(ErrorKind::CallStackOverflow);
+        }
+        self.call_stack.push(ret);
+        Ok(addr)
+    }
+
+    fn call_host_function(&mut self, number: i64) -> Result<(), ErrorKind> {
+        let host = self
+            .host_functions
+            .get_mut(&number)
+            .ok_or(ErrorKind::UnknownHostFunction(number))?;
+        let (args, results) = (host.args, host.results);
+        if self.sp < args {
+            return Err(ErrorKind::StackUnderflow);
+        }
+        let mut values = vec![W::default(); results];
+        let args = self.sp - args..self.sp;
+        (host.f)(&self.stack[args.clone()], &mut values, &mut self.memory)
+            .map_err(|code| ErrorKind::HostFunctionFailed(number, code))?;
+        self.sp = args.start;
+        self.assert_stack_free_space(results)?;
+        self.stack[self.sp..self.sp + results].copy_from_slice(&values);
+        self.sp += results;
+        Ok(())
+    }
+
+    fn read_memory(&mut self, addr: W) -> Result<W, ErrorKind> {
+        let addr = address(addr);
+        if let Some((device, offset)) = self.device_at(addr) {
+            return device
+                .read(offset)
+                .map(W::wrap)
+                .map_err(|_| ErrorKind::IOError);
         }
+        self.assert_memory_address(addr)?;
+        Ok(self.memory[addr])
+    }
+
+    fn write_memory(&mut self, addr: W, v: W) -> Result<(), ErrorKind> {
+        let addr = address(addr);
+        if let Some((device, offset)) = self.device_at(addr) {
+            return device
+                .write(offset, v.into())
+                .map_err(|_| ErrorKind::IOError);
+        }
+        self.assert_memory_address(addr)?;
+        self.memory[addr] = v;
+        Ok(())
+    }
+
+    /// Replaces the top two values `b` and `a`, `a` being the top, with `f(b, a)`.
+    #[inline(always)]
+    fn binary<F>(&mut self, f: F) -> Result<(), ErrorKind>
+    where
+        F: FnOnce(W, W) -> Result<W, ErrorKind>,
+    {
+        self.assert_stack_size(2)?;
+        let sp = self.sp;
+        self.stack[sp - 2] = f(self.stack[sp - 2], self.stack[sp - 1])?;
+        self.sp = sp - 1;
         Ok(())
     }
 
-    fn push(&mut self, v: i32) -> Result<(), VMError> {
+    /// Like [`binary`](Self::binary) on the words taken as floats.
+    #[inline(always)]
+    fn float_binary<F>(&mut self, f: F) -> Result<(), ErrorKind>
+    where
+        F: FnOnce(W::Float, W::Float) -> W::Float,
+    {
+        self.binary(|b, a| Ok(W::from_float(f(b.to_float(), a.to_float()))))
+    }
+
+    /// Replaces the top value `a` with `f(a)`.
+    #[inline(always)]
+    fn unary<F>(&mut self, f: F) -> Result<(), ErrorKind>
+    where
+        F: FnOnce(W) -> W,
+    {
+        self.assert_stack_size(1)?;
+        let top = &mut self.stack[self.sp - 1];
+        *top = f(*top);
+        Ok(())
+    }
+
+    fn push(&mut self, v: W) -> Result<(), ErrorKind> {
         self.assert_stack_free_space(1)?;
         self.stack[self.sp] = v;
         self.sp += 1;
         Ok(())
     }
 
-    fn pop(&mut self) -> Result<i32, VMError> {
+    fn pop(&mut self) -> Result<W, ErrorKind> {
         self.assert_stack_size(1)?;
         let v = self.stack[self.sp - 1];
         self.sp -= 1;
@@ -321,47 +1082,86 @@ impl VM {
         self.stack.len() - self.sp >= min
     }
 
-    fn assert_stack_free_space(&self, min: usize) -> Result<(), VMError> {
-        if !self.check_stack_free_space(min) {
-            Err(VMError::StackOverflow)
+    fn assert_stack_free_space(&mut self, min: usize) -> Result<(), ErrorKind> {
+        if !self.check_stack_free_space(min) && !self.grow_stack(min) {
+            Err(ErrorKind::StackOverflow)
         } else {
             Ok(())
         }
     }
 
+    /// Grows a growable stack to have room for `min` more values.
+    fn grow_stack(&mut self, min: usize) -> bool {
+        let needed = self.sp + min;
+        if !self.config.growable_stack || needed > self.config.max_stack_size {
+            return false;
+        }
+        let size = needed
+            .max(self.stack.len() * 2)
+            .min(self.config.max_stack_size);
+        resize(&mut self.stack, size);
+        true
+    }
+
     fn check_stack_size(&self, min: usize) -> bool {
         self.sp >= min
     }
 
-    fn assert_stack_size(&self, min: usize) -> Result<(), VMError> {
+    fn assert_stack_size(&self, min: usize) -> Result<(), ErrorKind> {
         if !self.check_stack_size(min) {
-            Err(VMError::CorruptStack)
+            Err(ErrorKind::StackUnderflow)
         } else {
             Ok(())
         }
     }
 
+    #[inline(always)]
+    fn device_at(&mut self, addr: usize) -> Option<(&mut dyn Device, usize)> {
+        if addr < self.bus.first_address() {
+            return None;
+        }
+        self.bus.device_at(addr)
+    }
+
     fn check_memory_address(&self, addr: usize) -> bool {
         addr < self.memory.len()
     }
 
-    fn assert_memory_address(&self, addr: usize) -> Result<(), VMError> {
+    fn assert_memory_address(&self, addr: usize) -> Result<(), ErrorKind> {
         if !self.check_memory_address(addr) {
-            Err(VMError::InvalidMemoryAddress)
+            Err(ErrorKind::InvalidMemoryAddress)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn check_jump_target(&self, addr: W) -> bool {
+        addr.is_literal() && address(addr) < self.program.len()
+    }
+
+    fn assert_jump_target(&self, addr: W) -> Result<(), ErrorKind> {
+        if !self.check_jump_target(addr) {
+            Err(ErrorKind::InvalidJumpTarget(addr.into()))
         } else {
             Ok(())
         }
     }
+}
+
+impl Default for VM {
+    fn default() -> Self {
+        VM::new()
+    }
+}
+
+fn resize<W: Word>(cells: &mut Box<[W]>, size: usize) {
+    let mut v = std::mem::take(cells).into_vec();
+    v.resize(size, W::default());
+    *cells = v.into_boxed_slice();
+}
 
-    // fn print_stack(&self) {
-    //     if self.sp == 0 {
-    //         return;
-    //     }
-    //     print!("[");
-    //     for v in &self.stack[0..self.sp - 1] {
-    //         print!("{v}, ");
-    //     }
-    //     print!("{}", self.stack[self.sp - 1]);
-    //     println!("]");
-    // }
+/// `addr` as an index, negative addresses becoming too large to be valid.
+#[inline(always)]
+fn address<W: Word>(addr: W) -> usize {
+    Into::<i64>::into(addr) as usize
 }
@@ -0,0 +1,192 @@
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+use std::ops::{Add, BitAnd, BitOr, BitXor, Div, Mul, Neg, Not, Sub};
+use std::str::FromStr;
+
+mod sealed {
+    pub trait Sealed {}
+
+    impl Sealed for i32 {}
+    impl Sealed for i64 {}
+}
+
+/// The type of the values a [`VM`](crate::VM) computes with, `i32` by default or `i64`.
+///
+/// Opcodes are the same negative numbers whatever the word size, so any program runs with
+/// either as long as its literals fit.
+pub trait Word:
+    Copy
+    + Default
+    + Eq
+    + Ord
+    + Hash
+    + Debug
+    + Display
+    + FromStr
+    + From<i32>
+    + Into<i64>
+    + TryFrom<i64>
+    + BitAnd<Output = Self>
+    + BitOr<Output = Self>
+    + BitXor<Output = Self>
+    + Not<Output = Self>
+    + Send
+    + Sync
+    + 'static
+    + sealed::Sealed
+{
+    /// The size of a word in bits, which is also how it is declared in program files.
+    const BITS: u32;
+
+    /// The floating-point type of the same size, which floating-point instructions take the
+    /// bits of words as.
+    type Float: Copy
+        + PartialOrd
+        + Display
+        + FromStr
+        + Add<Output = Self::Float>
+        + Sub<Output = Self::Float>
+        + Mul<Output = Self::Float>
+        + Div<Output = Self::Float>
+        + Neg<Output = Self::Float>;
+
+    fn wrapping_add(self, rhs: Self) -> Self;
+
+    fn wrapping_sub(self, rhs: Self) -> Self;
+
+    fn wrapping_mul(self, rhs: Self) -> Self;
+
+    fn wrapping_neg(self) -> Self;
+
+    fn checked_div(self, rhs: Self) -> Option<Self>;
+
+    fn wrapping_rem(self, rhs: Self) -> Self;
+
+    /// Shifts left by `rhs` modulo [`BITS`](Word::BITS).
+    fn wrapping_shl(self, rhs: u32) -> Self;
+
+    /// Shifts right arithmetically by `rhs` modulo [`BITS`](Word::BITS).
+    fn wrapping_shr(self, rhs: u32) -> Self;
+
+    /// Keeps the low [`BITS`](Word::BITS) bits of `v`.
+    fn wrap(v: i64) -> Self;
+
+    /// Appends the little-endian bytes of the word to `bytes`.
+    fn write_le(self, bytes: &mut Vec<u8>);
+
+    /// Reads a word from exactly `BITS / 8` little-endian bytes.
+    fn read_le(bytes: &[u8]) -> Self;
+
+    /// The float with the bits of the word.
+    fn to_float(self) -> Self::Float;
+
+    /// The word with the bits of `f`.
+    fn from_float(f: Self::Float) -> Self;
+
+    /// The float nearest to the value of the word.
+    fn int_to_float(self) -> Self::Float;
+
+    /// `f` rounded toward zero, saturating at the bounds of the word, with NaN becoming 0.
+    fn float_to_int(f: Self::Float) -> Self;
+
+    /// Whether the word is a literal rather than an instruction.
+    fn is_literal(self) -> bool {
+        self >= Self::default()
+    }
+
+    /// The instruction the word encodes, or `None` for literals and words too small to be an
+    /// opcode.
+    fn opcode(self) -> Option<i32> {
+        if self.is_literal() {
+            return None;
+        }
+        let v: i64 = self.into();
+        i32::try_from(v).ok()
+    }
+}
+
+macro_rules! impl_word {
+    ($t:ty, $f:ty) => {
+        impl Word for $t {
+            const BITS: u32 = <$t>::BITS;
+
+            type Float = $f;
+
+            #[inline(always)]
+            fn wrapping_add(self, rhs: Self) -> Self {
+                <$t>::wrapping_add(self, rhs)
+            }
+
+            #[inline(always)]
+            fn wrapping_sub(self, rhs: Self) -> Self {
+                <$t>::wrapping_sub(self, rhs)
+            }
+
+            #[inline(always)]
+            fn wrapping_mul(self, rhs: Self) -> Self {
+                <$t>::wrapping_mul(self, rhs)
+            }
+
+            #[inline(always)]
+            fn wrapping_neg(self) -> Self {
+                <$t>::wrapping_neg(self)
+            }
+
+            #[inline(always)]
+            fn checked_div(self, rhs: Self) -> Option<Self> {
+                <$t>::checked_div(self, rhs)
+            }
+
+            #[inline(always)]
+            fn wrapping_rem(self, rhs: Self) -> Self {
+                <$t>::wrapping_rem(self, rhs)
+            }
+
+            #[inline(always)]
+            fn wrapping_shl(self, rhs: u32) -> Self {
+                <$t>::wrapping_shl(self, rhs)
+            }
+
+            #[inline(always)]
+            fn wrapping_shr(self, rhs: u32) -> Self {
+                <$t>::wrapping_shr(self, rhs)
+            }
+
+            #[inline(always)]
+            fn wrap(v: i64) -> Self {
+                v as $t
+            }
+
+            fn write_le(self, bytes: &mut Vec<u8>) {
+                bytes.extend_from_slice(&self.to_le_bytes());
+            }
+
+            fn read_le(bytes: &[u8]) -> Self {
+                <$t>::from_le_bytes(bytes.try_into().unwrap())
+            }
+
+            #[inline(always)]
+            fn to_float(self) -> $f {
+                <$f>::from_bits(self as _)
+            }
+
+            #[inline(always)]
+            fn from_float(f: $f) -> Self {
+                f.to_bits() as $t
+            }
+
+            #[inline(always)]
+            fn int_to_float(self) -> $f {
+                self as $f
+            }
+
+            #[inline(always)]
+            fn float_to_int(f: $f) -> Self {
+                f as $t
+            }
+        }
+    };
+}
+
+impl_word!(i32, f32);
+impl_word!(i64, f64);
@@ -0,0 +1,219 @@
+//! Drives `svm debug` through its standard input.
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use svm::disasm::{disassemble, instruction_len};
+use svm::instructions::*;
+use svm::program::Program;
+use svm::source_map::{SourceLocation, SourceMap};
+use svm::symbols::SymbolTable;
+
+/// Doubles 4 in a subroutine and stores the result at memory[0].
+const PROGRAM: [i32; 9] = [4, 6, CALL, 0, STOR, HALT, DUP, ADD, RET];
+const SYMBOLS: &str = "0 start\n6 double\n";
+
+/// Runs the debugger on `PROGRAM` with `commands` as its input and returns its output.
+fn debug(name: &str, commands: &str) -> String {
+    debug_program(name, Program::new(PROGRAM.to_vec()), commands)
+}
+
+fn debug_program(name: &str, program: Program, commands: &str) -> String {
+    let path: PathBuf =
+        std::env::temp_dir().join(format!("svm-debug-{name}-{}", std::process::id()));
+    let path = path.to_str().unwrap().to_string();
+    let file = std::fs::File::create(&path).unwrap();
+    program.write(file).unwrap();
+    let sym_path = SymbolTable::sidecar_path(&path);
+    std::fs::write(&sym_path, SYMBOLS).unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_svm"))
+        .args(["debug", &path])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(commands.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&sym_path).unwrap();
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn session() {
+    let output = debug(
+        "session",
+        "break double\ncontinue\ncalls\nstack\nset stack 0 10\n\
+         continue\nmem 0\ninfo\nlist 0 3\nstep\nquit\n",
+    );
+    let expected = "\
+svm debugger, type 'help' for a list of commands
+0 <start>: 4
+(svm) breakpoint at 6 <double>
+(svm) breakpoint at 6 <double>
+6 <double>: DUP
+(svm)   returns to 3 <start+3>
+(svm) [4]
+(svm) (svm) program halted
+(svm)   [0] 20
+(svm) ip  6 <double>
+sp  0
+hf  true
+rf  false
+(svm)        :start
+       0  4
+       1  @double
+       2  CALL
+(svm) program halted
+(svm) ";
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn mistakes_and_repeats() {
+    let output = debug(
+        "mistakes",
+        "step\n\nstack\nbreak nowhere\nlist 100\nset mem 5000 1\nfly\n",
+    );
+    let expected = "\
+svm debugger, type 'help' for a list of commands
+0 <start>: 4
+(svm) 1 <start+1>: @double
+(svm) 2 <start+2>: CALL
+(svm) [4, 6]
+(svm) unknown label 'nowhere'
+(svm) address 100 is outside the program
+(svm) invalid memory address 5000
+(svm) invalid command 'fly', type 'help' for help
+(svm) ";
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn symbols() {
+    let symbols = SymbolTable::from_text(SYMBOLS).unwrap();
+    assert_eq!(symbols.address("double"), Some(6));
+    assert_eq!(symbols.enclosing(8), Some(("double", 6)));
+    let mut text = Vec::new();
+    symbols.write(&mut text).unwrap();
+    assert_eq!(text, SYMBOLS.as_bytes());
+    assert!(SymbolTable::from_text("six double\n").is_err());
+
+    let listing: Vec<_> = (0..PROGRAM.len())
+        .map(|addr| disassemble(&PROGRAM, addr, Some(&symbols)))
+        .collect();
+    assert_eq!(
+        listing,
+        ["4", "@double", "CALL", "@start", "STOR", "HALT", "DUP", "ADD", "RET"]
+    );
+    assert_eq!(disassemble(&[6, CALL], 0, None), "6");
+    assert_eq!(disassemble(&[-77], 0, None), "?? -77");
+
+    let program = [PUSH, -4, PUSH, 6, PUSH];
+    assert_eq!(disassemble(&program, 0, Some(&symbols)), "PUSH -4");
+    assert_eq!(disassemble(&program, 2, Some(&symbols)), "PUSH 6");
+    assert_eq!(disassemble(&program, 4, None), "PUSH");
+    let lens: Vec<_> = (0..program.len())
+        .map(|addr| instruction_len(&program, addr))
+        .collect();
+    assert_eq!(lens, [2, 1, 2, 1, 1]);
+}
+
+/// `PROGRAM` as assembled from `SOURCE`, one `(line, column)` per address.
+const SOURCE: &str = ":start\n\t4 @double call\n\t0 stor\n\thalt\n:double\n\tdup add\n\tret\n";
+const LOCATIONS: [(usize, usize); 9] = [
+    (2, 2),
+    (2, 4),
+    (2, 12),
+    (3, 2),
+    (3, 4),
+    (4, 2),
+    (6, 2),
+    (6, 6),
+    (7, 2),
+];
+
+/// Writes `SOURCE` to `double.asm` in a new directory and runs the debugger on `PROGRAM` with
+/// a source map of `locations` into it, returning the path of the source and the output.
+fn debug_source(name: &str, locations: &[(usize, usize)], commands: &str) -> (String, String) {
+    let dir = std::env::temp_dir().join(format!("svm-source-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("double.asm");
+    std::fs::write(&source, SOURCE).unwrap();
+    let source = source.to_str().unwrap().to_string();
+    let mut source_map = SourceMap::new(&source);
+    for (addr, &(line, column)) in locations.iter().enumerate() {
+        source_map.insert(addr, SourceLocation { line, column });
+    }
+    let program = Program {
+        source_map: Some(source_map),
+        ..Program::new(PROGRAM.to_vec())
+    };
+    let output = debug_program(name, program, commands);
+    std::fs::remove_dir_all(&dir).unwrap();
+    (source, output)
+}
+
+#[test]
+fn source_level() {
+    let (source, output) = debug_source(
+        "source",
+        &LOCATIONS,
+        "break double.asm:6\ncontinue\nbreak other.asm:6\nbreak double.asm:5\nstep 2\nquit\n",
+    );
+    let expected = format!(
+        "\
+svm debugger, type 'help' for a list of commands
+0 <start> ({source}:2:2): 4
+      2 | \t4 @double call
+(svm) breakpoint at 6 <double> ({source}:6:2)
+(svm) breakpoint at 6 <double> ({source}:6:2)
+6 <double> ({source}:6:2): DUP
+      6 | \tdup add
+(svm) unknown source file 'other.asm'
+(svm) no code at double.asm:5
+(svm) 8 <double+2> ({source}:7:2): RET
+      7 | \tret
+(svm) "
+    );
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn lines_outside_the_source() {
+    // line 0 comes before the first line and line 100 after the last, so neither is shown
+    let (source, output) = debug_source("outside", &[(0, 0), (100, 1)], "step\nquit\n");
+    let expected = format!(
+        "\
+svm debugger, type 'help' for a list of commands
+0 <start> ({source}:0:0): 4
+(svm) 1 <start+1> ({source}:100:1): @double
+(svm) "
+    );
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn source_maps() {
+    let text = "file double.asm\n0 2:2\n6 6:2\n";
+    let map = SourceMap::from_text(text).unwrap();
+    assert_eq!(map.file(), "double.asm");
+    assert_eq!(map.get(6), Some(SourceLocation { line: 6, column: 2 }));
+    assert_eq!(map.get(1), None);
+    assert_eq!(map.address_of_line(6), Some(6));
+    assert_eq!(map.describe(0).unwrap(), "double.asm:2:2");
+    let mut written = Vec::new();
+    map.write(&mut written).unwrap();
+    assert_eq!(written, text.as_bytes());
+
+    assert!(SourceMap::from_text("0 2:2\n").is_err());
+    assert!(SourceMap::from_text("file a.asm\n0 2\n").is_err());
+}
@@ -0,0 +1,196 @@
+//! Maps devices over data memory and reaches them with `LOAD` and `STOR`.
+
+use std::cell::RefCell;
+use std::io;
+use std::rc::Rc;
+
+use svm::device::{Device, OverlapError};
+use svm::instructions::*;
+use svm::io::BufferIo;
+use svm::program::Program;
+use svm::{ErrorKind, ExitReason, VMConfig, VM};
+
+/// An access made to a device: its name, the offset and the value read or written.
+#[derive(Debug, PartialEq)]
+enum Access {
+    Read(&'static str, usize, i64),
+    Write(&'static str, usize, i64),
+}
+
+type Log = Rc<RefCell<Vec<Access>>>;
+
+/// A device that logs its accesses. Reading gives its id times 100 plus the offset.
+struct Probe {
+    name: &'static str,
+    id: i64,
+    cells: usize,
+    log: Log,
+}
+
+impl Device for Probe {
+    fn cells(&self) -> usize {
+        self.cells
+    }
+
+    fn read(&mut self, offset: usize) -> io::Result<i64> {
+        let v = self.id * 100 + offset as i64;
+        self.log
+            .borrow_mut()
+            .push(Access::Read(self.name, offset, v));
+        Ok(v)
+    }
+
+    fn write(&mut self, offset: usize, v: i64) -> io::Result<()> {
+        self.log
+            .borrow_mut()
+            .push(Access::Write(self.name, offset, v));
+        Ok(())
+    }
+}
+
+/// A device every access to fails.
+struct Broken;
+
+impl Device for Broken {
+    fn cells(&self) -> usize {
+        1
+    }
+
+    fn read(&mut self, _offset: usize) -> io::Result<i64> {
+        Err(io::Error::other("broken"))
+    }
+
+    fn write(&mut self, _offset: usize, _v: i64) -> io::Result<()> {
+        Err(io::Error::other("broken"))
+    }
+}
+
+fn probe(name: &'static str, id: i64, cells: usize, log: &Log) -> Probe {
+    Probe {
+        name,
+        id,
+        cells,
+        log: log.clone(),
+    }
+}
+
+/// A VM with 8 cells of memory running `code`.
+fn vm(code: Vec<i32>) -> VM<BufferIo> {
+    let mut vm = VM::with_config_and_io(VMConfig::new().memory_size(8), BufferIo::new(""));
+    vm.load_program(Program::new(code));
+    vm
+}
+
+#[test]
+fn overlapping_ranges() {
+    let log = Log::default();
+    let mut vm = vm(vec![HALT]);
+    vm.map_device(100, probe("a", 1, 4, &log)).unwrap();
+    vm.map_device(110, probe("b", 2, 2, &log)).unwrap();
+    // inside, across either end of and around an existing range
+    for (start, cells) in [(100, 1), (103, 1), (98, 3), (103, 5), (90, 30), (111, 5)] {
+        assert_eq!(
+            vm.map_device(start, probe("c", 3, cells, &log)),
+            Err(OverlapError { start }),
+            "{cells} cells at {start}"
+        );
+    }
+    assert_eq!(
+        vm.map_device(usize::MAX - 1, probe("c", 3, 4, &log)),
+        Ok(())
+    );
+    assert_eq!(
+        vm.map_device(usize::MAX - 3, probe("d", 4, 3, &log)),
+        Err(OverlapError {
+            start: usize::MAX - 3
+        })
+    );
+    // right before, between and right after
+    vm.map_device(99, probe("e", 5, 1, &log)).unwrap();
+    vm.map_device(104, probe("f", 6, 6, &log)).unwrap();
+    vm.map_device(112, probe("g", 7, 1, &log)).unwrap();
+    assert_eq!(
+        OverlapError { start: 105 }.to_string(),
+        "device at 105 overlaps another device"
+    );
+}
+
+#[test]
+fn accesses_reach_the_right_device() {
+    let log = Log::default();
+    let fused = [5, 8, STOR, 20, LOAD, 9, LOAD, 7, 20, STOR];
+    let computed = [6, 4, 5, ADD, STOR, 10, 10, ADD, LOAD];
+    let memory = [1, 2, STOR, 2, LOAD, HALT];
+    let mut vm = vm([&fused[..], &computed, &memory].concat());
+    vm.map_device(8, probe("a", 1, 2, &log)).unwrap();
+    vm.map_device(20, probe("b", 2, 1, &log)).unwrap();
+    assert_eq!(vm.run().unwrap(), ExitReason::Halted);
+    assert_eq!(
+        *log.borrow(),
+        [
+            Access::Write("a", 0, 5),
+            Access::Read("b", 0, 200),
+            Access::Read("a", 1, 101),
+            Access::Write("b", 0, 7),
+            Access::Write("a", 1, 6),
+            Access::Read("b", 0, 200),
+        ]
+    );
+    assert_eq!(vm.stack(), [200, 101, 200, 1]);
+    assert_eq!(vm.memory(), [0, 0, 1, 0, 0, 0, 0, 0]);
+}
+
+#[test]
+fn devices_shadow_memory() {
+    let log = Log::default();
+    let mut vm = vm(vec![9, 3, STOR, 3, LOAD, 9, 4, STOR, HALT]);
+    vm.map_device(3, probe("a", 1, 1, &log)).unwrap();
+    vm.run().unwrap();
+    assert_eq!(
+        *log.borrow(),
+        [Access::Write("a", 0, 9), Access::Read("a", 0, 100)]
+    );
+    assert_eq!(vm.stack(), [100]);
+    assert_eq!(vm.memory(), [0, 0, 0, 0, 9, 0, 0, 0]);
+}
+
+#[test]
+fn unmapped_addresses_past_memory() {
+    let log = Log::default();
+    for code in [vec![9, 12, STOR], vec![12, LOAD], vec![6, 6, ADD, LOAD]] {
+        let mut vm = vm(code);
+        vm.map_device(8, probe("a", 1, 2, &log)).unwrap();
+        vm.map_device(20, probe("b", 2, 1, &log)).unwrap();
+        assert_eq!(vm.run().unwrap_err().kind, ErrorKind::InvalidMemoryAddress);
+    }
+    assert!(log.borrow().is_empty());
+}
+
+#[test]
+fn device_errors() {
+    for code in [vec![1, 8, STOR], vec![8, LOAD], vec![4, 4, ADD, LOAD]] {
+        let mut vm = vm(code);
+        vm.map_device(8, Broken).unwrap();
+        assert_eq!(vm.run().unwrap_err().kind, ErrorKind::IOError);
+    }
+}
+
+#[test]
+fn random_and_timer() {
+    use svm::device::{Random, Timer};
+
+    let mut vm = vm(vec![
+        100, LOAD, 100, LOAD, 42, 100, STOR, 100, LOAD, 101, LOAD, HALT,
+    ]);
+    vm.map_device(100, Random::new(42)).unwrap();
+    vm.map_device(101, Timer::new()).unwrap();
+    vm.run().unwrap();
+    let mut random = Random::new(42);
+    let first = random.read(0).unwrap();
+    let second = random.read(0).unwrap();
+    // random values fit in 31 bits, so 32-bit words hold them unchanged
+    let stack: Vec<i64> = vm.stack().iter().map(|&v| v.into()).collect();
+    assert_eq!(stack[..3], [first, second, first]);
+    assert_ne!(first, second);
+    assert!(stack.iter().all(|&v| v >= 0));
+}
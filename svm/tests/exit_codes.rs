@@ -0,0 +1,56 @@
+//! Checks the exit codes of the `svm` binary.
+
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use svm::instructions::*;
+use svm::program::Program;
+use svm::Word;
+
+/// Runs `code` with `svm` and returns its exit code.
+fn svm<W: Word>(name: &str, code: Vec<W>, args: &[&str]) -> i32 {
+    let path: PathBuf =
+        std::env::temp_dir().join(format!("svm-exit-{name}-{}", std::process::id()));
+    let file = std::fs::File::create(&path).unwrap();
+    Program::new(code).write(file).unwrap();
+    let status = Command::new(env!("CARGO_BIN_EXE_svm"))
+        .args(args)
+        .arg(&path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .unwrap();
+    std::fs::remove_file(path).unwrap();
+    status.code().unwrap()
+}
+
+#[test]
+fn exit_statuses() {
+    assert_eq!(svm("end", vec![NOP], &[]), 0);
+    assert_eq!(svm("zero", vec![0, EXIT], &[]), 0);
+    assert_eq!(svm("seven", vec![7, EXIT], &[]), 7);
+    assert_eq!(svm("max", vec![63, EXIT], &[]), 63);
+    // the codes above 63 are svm's own, and truncating 256 would make it a success
+    assert_eq!(svm("reserved", vec![65, EXIT], &[]), 63);
+    assert_eq!(svm("big", vec![256, EXIT], &[]), 63);
+    assert_eq!(svm("negative", vec![PUSH, -1, EXIT], &[]), 63);
+    // the program header selects 64-bit words, in which 1 << 33 does not wrap to 0
+    let wide: Vec<i64> = vec![1 << 33, 1 << 32, DIV.into(), EXIT.into()];
+    assert_eq!(svm("wide", wide, &[]), 2);
+}
+
+#[test]
+fn failures() {
+    assert_eq!(svm("underflow", vec![ADD], &[]), 70);
+    assert_eq!(svm("memory", vec![PUSH, -1, LOAD], &[]), 72);
+    assert_eq!(svm("division", vec![1, 0, DIV], &[]), 75);
+    assert_eq!(svm("usage", vec![HALT], &["--max-steps", "many"]), 64);
+}
+
+#[test]
+fn step_limit() {
+    assert_eq!(svm("halt", vec![HALT], &[]), 0);
+    assert_eq!(svm("steps", vec![0, JMP], &["--max-steps", "10"]), 65);
+    assert_eq!(svm("enough", vec![1, POP, HALT], &["--max-steps", "3"]), 0);
+}
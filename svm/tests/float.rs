@@ -0,0 +1,126 @@
+//! Runs the floating-point instructions on 32-bit and 64-bit words.
+
+use svm::instructions::*;
+use svm::io::BufferIo;
+use svm::program::Program;
+use svm::{ErrorKind, VMConfig, VMError, VM};
+
+/// The word holding the bits of `f`. Only non-negative floats are literals.
+fn f(f: f32) -> i32 {
+    f.to_bits() as i32
+}
+
+fn run(code: Vec<i32>, input: &str) -> (Result<Vec<f32>, VMError>, String) {
+    let mut vm = VM::with_config_and_io(VMConfig::new(), BufferIo::new(input));
+    vm.load_program(Program::new(code));
+    let result = vm.run().map(|_| {
+        (vm.stack().iter())
+            .map(|&w| f32::from_bits(w as u32))
+            .collect()
+    });
+    (result, vm.io().output().to_string())
+}
+
+fn floats(code: Vec<i32>) -> Vec<f32> {
+    run(code, "").0.unwrap()
+}
+
+/// The integer stack after running `code`.
+fn ints(code: Vec<i32>) -> Vec<i32> {
+    let mut vm = VM::with_program(code);
+    vm.run().unwrap();
+    vm.stack().to_vec()
+}
+
+#[test]
+fn arithmetic() {
+    assert_eq!(floats(vec![f(1.5), f(2.25), FADD]), [3.75]);
+    assert_eq!(floats(vec![f(1.5), f(2.25), FSUB]), [-0.75]);
+    assert_eq!(floats(vec![f(1.5), f(2.25), FMUL]), [3.375]);
+    assert_eq!(floats(vec![f(1.5), f(0.5), FDIV]), [3.0]);
+    assert_eq!(floats(vec![f(1.5), FNEG]), [-1.5]);
+    assert_eq!(floats(vec![PUSH, f(-1.5), f(2.0), FMUL]), [-3.0]);
+    // division by zero does not trap
+    assert_eq!(floats(vec![f(1.0), f(0.0), FDIV]), [f32::INFINITY]);
+    assert!(floats(vec![f(0.0), f(0.0), FDIV])[0].is_nan());
+}
+
+#[test]
+fn comparisons() {
+    let nan = [f(0.0), f(0.0), FDIV];
+    for (code, expected) in [
+        (vec![f(1.0), f(2.0), FLT], 1),
+        (vec![f(2.0), f(1.0), FLT], 0),
+        (vec![f(2.0), f(2.0), FLE], 1),
+        (vec![f(2.0), f(2.0), FEQ], 1),
+        (vec![f(0.0), f(0.0), FNEG, FEQ], 1),
+        ([&nan[..], &nan, &[FEQ]].concat(), 0),
+        ([&nan[..], &[f(1.0), FLE]].concat(), 0),
+    ] {
+        assert_eq!(ints(code.clone()), [expected], "{code:?}");
+    }
+}
+
+#[test]
+fn conversions() {
+    assert_eq!(floats(vec![7, ITOF]), [7.0]);
+    assert_eq!(floats(vec![7, NEG, ITOF]), [-7.0]);
+    // the nearest float to 2^31 - 1
+    assert_eq!(floats(vec![i32::MAX, ITOF]), [2147483648.0]);
+    assert_eq!(ints(vec![f(2.7), FTOI, f(2.7), FNEG, FTOI]), [2, -2]);
+    assert_eq!(
+        ints(vec![f(1e20), FTOI, f(1e20), FNEG, FTOI]),
+        [i32::MAX, i32::MIN]
+    );
+    assert_eq!(ints(vec![f(0.0), f(0.0), FDIV, FTOI]), [0]);
+}
+
+#[test]
+fn input_and_output() {
+    let (stack, output) = run(vec![FIN, FIN, FADD, DUP, FOUT], " 2.5\n-0.25 ");
+    assert_eq!(stack.unwrap(), [2.25]);
+    assert_eq!(output, "2.25\n");
+    let (_, output) = run(vec![f(1.0), f(3.0), FDIV, FOUT, f(1e10), FOUT], "");
+    assert_eq!(output, "0.33333334\n10000000000\n");
+
+    let (result, _) = run(vec![FIN], "two");
+    assert_eq!(result.unwrap_err().kind, ErrorKind::IOError);
+    let (result, _) = run(vec![FIN], "");
+    assert_eq!(result.unwrap_err().kind, ErrorKind::IOError);
+}
+
+#[test]
+fn wide_floats() {
+    let wide = |f: f64| f.to_bits() as i64;
+    let code = vec![
+        wide(0.1),
+        wide(0.2),
+        FADD.into(),
+        FOUT.into(),
+        1 << 53,
+        1,
+        ADD.into(),
+        ITOF.into(),
+        FTOI.into(),
+    ];
+    let mut vm: VM<BufferIo, i64> = VM::with_config_and_io(VMConfig::new(), BufferIo::new(""));
+    vm.load_program(Program::new(code));
+    vm.run().unwrap();
+    assert_eq!(vm.io().output(), "0.30000000000000004\n");
+    // 2^53 + 1 has no f64, so it rounds to 2^53
+    assert_eq!(vm.stack(), [1 << 53]);
+}
+
+#[test]
+fn circle() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../examples/circle/circle");
+    for (input, expected) in [
+        ("2.5", "19.634954\n15.707964\n19\n"),
+        ("-1", "3.1415927\n6.2831855\n3\n"),
+    ] {
+        let mut vm = VM::with_io(BufferIo::new(input));
+        vm.load(path).unwrap();
+        vm.run().unwrap();
+        assert_eq!(vm.io().output(), expected, "radius {input}");
+    }
+}
@@ -0,0 +1,76 @@
+//! Calls host functions with `SYS`.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use svm::instructions::*;
+use svm::{ErrorKind, ExitReason, VM};
+
+#[test]
+fn arguments_and_results_in_order() {
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let mut vm = VM::with_program(vec![9, 1, 2, 3, 7, SYS, HALT]);
+    let log = seen.clone();
+    vm.register_host_function(7, 3, 2, move |args, results, memory| {
+        log.borrow_mut().extend_from_slice(args);
+        memory[0] = args[0];
+        // the difference of the first two, then the last
+        results[0] = args[0] - args[1];
+        results[1] = args[2];
+        Ok(())
+    });
+    assert_eq!(vm.run().unwrap(), ExitReason::Halted);
+    assert_eq!(*seen.borrow(), [1, 2, 3]);
+    assert_eq!(vm.stack(), [9, -1, 3]);
+    assert_eq!(vm.memory()[0], 1);
+}
+
+#[test]
+fn replaced_function() {
+    let mut vm = VM::with_program(vec![4, 1, SYS, 1, SYS, HALT]);
+    vm.register_host_function(1, 1, 1, |args, results, _| {
+        results[0] = args[0] + 1;
+        Ok(())
+    });
+    vm.register_host_function(1, 1, 1, |args, results, _| {
+        results[0] = args[0] * 10;
+        Ok(())
+    });
+    vm.run().unwrap();
+    assert_eq!(vm.stack(), [400]);
+}
+
+#[test]
+fn unknown_call_number() {
+    let mut vm = VM::with_program(vec![5, 8, SYS, HALT]);
+    vm.register_host_function(7, 0, 0, |_, _, _| Ok(()));
+    let e = vm.run().unwrap_err();
+    assert_eq!(e.kind, ErrorKind::UnknownHostFunction(8));
+    assert_eq!((e.ip, e.opcode), (2, Some(SYS as i64)));
+    assert_eq!(vm.stack(), [5]);
+}
+
+#[test]
+fn too_few_arguments() {
+    let called = Rc::new(RefCell::new(false));
+    let mut vm = VM::with_program(vec![1, 2, 7, SYS, HALT]);
+    let flag = called.clone();
+    vm.register_host_function(7, 3, 0, move |_, _, _| {
+        *flag.borrow_mut() = true;
+        Ok(())
+    });
+    let e = vm.run().unwrap_err();
+    assert_eq!(e.kind, ErrorKind::StackUnderflow);
+    assert_eq!(e.ip, 3);
+    assert!(!*called.borrow());
+    assert_eq!(vm.stack(), [1, 2]);
+}
+
+#[test]
+fn failure() {
+    let mut vm = VM::with_program(vec![1, 7, SYS, HALT]);
+    vm.register_host_function(7, 1, 1, |_, _, _| Err(42));
+    let e = vm.run().unwrap_err();
+    assert_eq!(e.kind, ErrorKind::HostFunctionFailed(7, 42));
+    assert_eq!(e.kind.exit_code(), 77);
+}
@@ -0,0 +1,127 @@
+//! Drives `IN` and `OUT` through the I/O backends.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use svm::instructions::*;
+use svm::io::{BufferIo, CallbackIo, Io};
+use svm::program::Program;
+use svm::{ErrorKind, VM};
+
+fn loaded<I: Io>(io: I, code: Vec<i32>) -> VM<I> {
+    let mut vm = VM::with_io(io);
+    vm.load_program(Program::new(code));
+    vm
+}
+
+#[test]
+fn buffer() {
+    let mut vm = loaded(BufferIo::new(" 3\n-4 x"), vec![IN, IN, ADD, OUT, IN]);
+    assert_eq!(vm.run().unwrap_err().kind, ErrorKind::IOError);
+    assert_eq!(vm.io().output(), "-1\n");
+
+    // raw output writes characters
+    let mut vm = loaded(BufferIo::new(""), vec![72, 105, RF, OVR, OUT, OUT]);
+    vm.run().unwrap();
+    assert_eq!(vm.into_io().output(), "Hi");
+}
+
+#[test]
+fn raw_input() {
+    let mut vm = loaded(BufferIo::new("aö✓"), vec![RF, IN, IN, IN, IN, IN]);
+    vm.run().unwrap();
+    assert_eq!(vm.stack(), [97, 246, 0x2713, EOF, EOF]);
+
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../examples/echo/echo");
+    let mut vm = VM::with_io(BufferIo::new("two\nlines"));
+    vm.load(path).unwrap();
+    vm.run().unwrap();
+    assert_eq!(vm.io().output(), "two\nlines");
+}
+
+#[test]
+fn strings_in_data() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../examples/strings/strings");
+    let mut vm = VM::with_io(BufferIo::new(""));
+    vm.load(path).unwrap();
+    vm.run().unwrap();
+    assert_eq!(vm.io().output(), "Hello from the data segment!\nGoodbye.\n");
+}
+
+#[test]
+fn raw_output() {
+    let code = vec![
+        RF,
+        0x41,
+        OUT,
+        0xF6,
+        OUT,
+        0x2713,
+        OUT,
+        0x141,
+        OUT,
+        0xD800,
+        OUT,
+        1 << 30,
+        OUT,
+    ];
+    let mut vm = loaded(BufferIo::new(""), code);
+    vm.run().unwrap();
+    assert_eq!(vm.io().output(), "Aö✓Ł\u{FFFD}\u{FFFD}");
+
+    // what raw input reads, raw output writes back
+    let text = "dög ✓ 𝄞";
+    let mut code = vec![RF];
+    for _ in text.chars() {
+        code.extend([IN, OUT]);
+    }
+    let mut vm = loaded(BufferIo::new(text), code);
+    vm.run().unwrap();
+    assert_eq!(vm.io().output(), text);
+}
+
+#[test]
+fn more_input() {
+    let mut vm = loaded(BufferIo::new(""), vec![IN, OUT]);
+    assert_eq!(vm.run().unwrap_err().kind, ErrorKind::IOError);
+    assert_eq!(vm.ip(), 0);
+    vm.io_mut().push_input("12\n");
+    vm.run().unwrap();
+    assert_eq!(vm.io_mut().take_output(), "12\n");
+    assert_eq!(vm.io().output(), "");
+}
+
+#[test]
+fn callbacks() {
+    let written = Rc::new(RefCell::new(Vec::new()));
+    let (numbers, chars) = (written.clone(), written.clone());
+    let mut next = 5;
+    let io = CallbackIo::new()
+        .on_read_number(move || {
+            next += 1;
+            Ok(next)
+        })
+        .on_write_number(move |v| {
+            numbers.borrow_mut().push(v.to_string());
+            Ok(())
+        })
+        .on_write_char(move |c| {
+            chars.borrow_mut().push(c.to_string());
+            Ok(())
+        });
+    let mut vm = loaded(io, vec![IN, IN, MUL, OUT, RF, 120, OUT]);
+    vm.run().unwrap();
+    assert_eq!(*written.borrow(), ["42", "x"]);
+
+    // without callbacks input is exhausted and output discarded
+    let mut vm = loaded(CallbackIo::new(), vec![1, OUT, IN]);
+    assert_eq!(vm.run().unwrap_err().kind, ErrorKind::IOError);
+}
+
+#[test]
+fn failed_flush() {
+    let io = CallbackIo::new().on_flush(|| Err(std::io::ErrorKind::BrokenPipe.into()));
+    let mut vm = loaded(io, vec![1, OUT, HALT]);
+    assert_eq!(vm.run().unwrap_err().kind, ErrorKind::IOError);
+    assert!(vm.hf());
+}
@@ -0,0 +1,135 @@
+//! Runs programs with and without the JIT and checks that they end in the same state, with
+//! budgets that run out at every kind of place, including inside compiled blocks.
+
+#![cfg(feature = "jit")]
+
+use std::path::Path;
+
+use svm::instructions::*;
+use svm::io::BufferIo;
+use svm::program::Program;
+use svm::{ExitReason, VMConfig, VMError, VM};
+
+/// Everything a run can be observed by.
+#[derive(Debug, PartialEq)]
+struct Outcome {
+    result: Result<ExitReason, VMError>,
+    output: String,
+    stack: Vec<i32>,
+    memory: Vec<i32>,
+    call_stack: Vec<usize>,
+    ip: usize,
+    fuel: Option<u64>,
+}
+
+/// Runs `program` on `input`, resuming every `chunk` instructions if given until the program
+/// stops or its fuel runs out.
+fn run(program: &Program, config: VMConfig, input: &str, jit: bool, chunk: Option<u64>) -> Outcome {
+    let mut vm = VM::with_config_and_io(config, BufferIo::new(input));
+    vm.load_program(program.clone());
+    if jit {
+        vm.enable_jit();
+    }
+    let result = match chunk {
+        None => vm.run(),
+        Some(n) => loop {
+            match vm.run_for(n) {
+                Ok(ExitReason::Yielded) if vm.fuel() != Some(0) => {}
+                result => break result,
+            }
+        },
+    };
+    Outcome {
+        result,
+        output: vm.io().output().to_string(),
+        stack: vm.stack().to_vec(),
+        memory: vm.memory().to_vec(),
+        call_stack: vm.call_stack().to_vec(),
+        ip: vm.ip(),
+        fuel: vm.fuel(),
+    }
+}
+
+fn check(name: &str, program: &Program, config: VMConfig, inputs: &[&str]) {
+    // a limit keeps programs that never stop finite
+    let limit = 200_000;
+    let fuels = [1, 2, 3, 63, 64, 65, 100, 257, 1000, 4099, limit];
+    let chunks = [
+        None,
+        Some(1),
+        Some(2),
+        Some(3),
+        Some(7),
+        Some(13),
+        Some(64),
+        Some(1000),
+    ];
+    for input in inputs {
+        for fuel in fuels {
+            for chunk in chunks {
+                let config = config.max_steps(Some(fuel));
+                assert_eq!(
+                    run(program, config, input, true, chunk),
+                    run(program, config, input, false, chunk),
+                    "{name} on {input:?} with fuel {fuel} in chunks of {chunk:?}"
+                );
+            }
+        }
+        assert_eq!(
+            run(program, config.max_steps(Some(limit)), input, true, None),
+            run(program, config.max_steps(Some(limit)), input, false, None),
+            "{name} on {input:?}"
+        );
+    }
+}
+
+#[test]
+fn examples() {
+    let sequence: String = std::iter::once(300)
+        .chain((0..300).map(|i| i * 7919 % 10007))
+        .map(|v| format!("{v}\n"))
+        .collect();
+    let text = "the quick brown fox\njumps over\tthe lazy dög ✓\n\n  end";
+    let cases: &[(&str, &[&str])] = &[
+        ("hello", &[""]),
+        ("sum", &["3\n4\n", "x\n"]),
+        ("gcd", &["48\n18\n", "832040\n514229\n", "7\n0\n"]),
+        ("negtest", &["-5\n", "5\n"]),
+        ("fib", &["0\n", "20\n", "40\n"]),
+        ("echo", &["", text]),
+        ("wc", &["", text]),
+        ("isort", &[&sequence, "3\n1\n2\n"]),
+        ("ssort", &[&sequence, "3\n1\n2\n"]),
+        ("strings", &[""]),
+        ("dice", &[""]),
+        ("eternal", &[""]),
+        ("circle", &["2.5\n"]),
+    ];
+    let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("../examples");
+    for (name, inputs) in cases {
+        let bytes = std::fs::read(examples.join(name).join(name)).unwrap();
+        let program = Program::from_bytes(&bytes).unwrap();
+        check(name, &program, VMConfig::new(), inputs);
+    }
+}
+
+#[test]
+fn failures_in_compiled_code() {
+    // writes i * i to memory[i] until i is past the end of memory
+    let memory = vec![
+        0, LOAD, 1, ADD, DUP, 0, STOR, DUP, DUP, MUL, SWP, STOR, 0, JMP,
+    ];
+    // pushes until the growable stack is full
+    let stack = vec![1, 2, XOR, 0, JMP];
+    // divides by a counter going down to 0
+    let division = vec![
+        50, 0, STOR, 100, 0, LOAD, DIV, POP, 0, LOAD, DEC, 0, STOR, 3, JMP,
+    ];
+    let config = VMConfig::new()
+        .memory_size(300)
+        .growable_stack(true)
+        .max_stack_size(5000);
+    for (name, code) in [("memory", memory), ("stack", stack), ("division", division)] {
+        check(name, &Program::new(code), config, &[""]);
+    }
+}
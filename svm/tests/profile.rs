@@ -0,0 +1,82 @@
+//! Profiles a program that calls a subroutine.
+
+use svm::instructions::*;
+use svm::profile::Profiler;
+use svm::symbols::SymbolTable;
+use svm::VM;
+
+/// Stores to memory[5], then calls a subroutine storing to memory[6] twice.
+const PROGRAM: [i32; 14] = [
+    1, 5, STOR, 10, CALL, 10, CALL, 5, LOAD, HALT, 1, 6, STOR, RET,
+];
+
+fn profiled() -> VM {
+    let mut vm = VM::with_program(PROGRAM.to_vec());
+    vm.set_profiler(Some(Profiler::new()));
+    vm.run().unwrap();
+    vm
+}
+
+fn symbols() -> SymbolTable {
+    SymbolTable::from_text("0 start\n10 sub\n").unwrap()
+}
+
+#[test]
+fn counts() {
+    let vm = profiled();
+    let profiler = vm.profiler().unwrap();
+    assert_eq!(profiler.total(), 18);
+    assert_eq!(
+        (profiler.count(0), profiler.count(10), profiler.count(13)),
+        (1, 2, 2)
+    );
+    assert_eq!(profiler.count(100), 0);
+    let opcodes: Vec<_> = profiler.opcodes().iter().map(|(&k, &v)| (k, v)).collect();
+    assert_eq!(
+        opcodes,
+        [
+            ("CALL", 2),
+            ("HALT", 1),
+            ("LIT", 9),
+            ("LOAD", 1),
+            ("RET", 2),
+            ("STOR", 3)
+        ]
+    );
+    assert_eq!(profiler.max_depth(), 2);
+    assert_eq!(
+        profiler.touched().iter().copied().collect::<Vec<_>>(),
+        [5, 6]
+    );
+}
+
+#[test]
+fn collapsed() {
+    let vm = profiled();
+    let profiler = vm.profiler().unwrap();
+    let mut out = Vec::new();
+    profiler
+        .write_collapsed(&mut out, Some(&symbols()))
+        .unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "main 10\nmain;sub 8\n");
+    let mut out = Vec::new();
+    profiler.write_collapsed(&mut out, None).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "main 10\nmain;10 8\n");
+}
+
+#[test]
+fn report() {
+    let vm = profiled();
+    let mut out = Vec::new();
+    (vm.profiler().unwrap())
+        .report(&mut out, vm.program(), Some(&symbols()))
+        .unwrap();
+    let report = String::from_utf8(out).unwrap();
+    let lines: Vec<_> = report.lines().collect();
+    assert_eq!(lines[0], "instructions executed  18");
+    assert_eq!(lines[1], "max stack depth        2");
+    assert_eq!(lines[2], "memory cells touched   2 (5..=6)");
+    assert_eq!(lines[4], "hot addresses:");
+    assert_eq!(lines[5], "           2  11.11%  10 <sub>                 1");
+    assert!(lines.contains(&"           9  50.00%  LIT"));
+}
@@ -0,0 +1,126 @@
+//! Reads and writes program files, intact and damaged.
+
+use svm::instructions::*;
+use svm::program::Program;
+use svm::source_map::{SourceLocation, SourceMap};
+use svm::symbols::SymbolTable;
+use svm::{VMConfig, VM};
+
+/// The CRC-32 program files end with.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+/// Replaces the checksum at the end of `bytes` with the one of the rest.
+fn reseal(bytes: &mut [u8]) {
+    let at = bytes.len() - 4;
+    let checksum = crc32(&bytes[..at]);
+    bytes[at..].copy_from_slice(&checksum.to_le_bytes());
+}
+
+fn program() -> Program {
+    let mut symbols = SymbolTable::new();
+    symbols.insert("start", 0);
+    let mut source_map = SourceMap::new("test.asm");
+    source_map.insert(0, SourceLocation { line: 1, column: 1 });
+    Program {
+        code: vec![1, 2, ADD, OUT, HALT],
+        data: vec![7, 8],
+        symbols: Some(symbols),
+        source_map: Some(source_map),
+        stack_size: Some(64),
+        memory_size: None,
+    }
+}
+
+fn error(bytes: &[u8]) -> String {
+    Program::<i32>::from_bytes(bytes).unwrap_err().to_string()
+}
+
+#[test]
+fn round_trip() {
+    let program = program();
+    let bytes = program.to_bytes().unwrap();
+    assert_eq!(Program::from_bytes(&bytes).unwrap(), program);
+    assert_eq!(Program::from_legacy_bytes(&bytes).unwrap(), program);
+
+    let wide = Program::<i64>::new(vec![1 << 40, OUT.into()]);
+    let bytes = wide.to_bytes().unwrap();
+    assert_eq!(svm::program::word_bits(&bytes), 64);
+    assert_eq!(Program::from_bytes(&bytes).unwrap(), wide);
+    assert_eq!(error(&bytes), "program has 64-bit words, expected 32-bit");
+}
+
+#[test]
+fn wrong_magic() {
+    let mut bytes = program().to_bytes().unwrap();
+    bytes[0] = b'X';
+    reseal(&mut bytes);
+    assert_eq!(error(&bytes), "not an svm program");
+}
+
+#[test]
+fn unknown_version() {
+    let mut bytes = program().to_bytes().unwrap();
+    bytes[4..8].copy_from_slice(&7u32.to_le_bytes());
+    reseal(&mut bytes);
+    assert_eq!(error(&bytes), "unsupported program version 7");
+}
+
+#[test]
+fn truncated_header() {
+    let bytes = program().to_bytes().unwrap();
+    assert_eq!(error(&bytes[..12]), "truncated program header");
+    assert_eq!(error(&bytes[..18]), "truncated program header");
+}
+
+#[test]
+fn truncated_section() {
+    let mut bytes = program().to_bytes().unwrap();
+    // the code section's length follows the 20 byte header and its kind
+    bytes[24..28].copy_from_slice(&1000u32.to_le_bytes());
+    reseal(&mut bytes);
+    assert_eq!(error(&bytes), "truncated section");
+
+    let mut bytes = program().to_bytes().unwrap();
+    let at = bytes.len() - 4;
+    bytes.splice(at - 3..at, []);
+    reseal(&mut bytes);
+    assert_eq!(error(&bytes), "truncated section");
+}
+
+#[test]
+fn checksum_mismatch() {
+    let mut bytes = program().to_bytes().unwrap();
+    bytes[30] ^= 1;
+    assert_eq!(error(&bytes), "program checksum mismatch");
+
+    let bytes = program().to_bytes().unwrap();
+    let len = bytes.len();
+    assert_eq!(error(&bytes[..len - 1]), "program checksum mismatch");
+}
+
+#[test]
+fn legacy_only_with_the_flag() {
+    let code = [1, 2, ADD, HALT];
+    let headerless: Vec<u8> = code.iter().flat_map(|w: &i32| w.to_le_bytes()).collect();
+
+    assert_eq!(error(&headerless), "not an svm program");
+    let program = Program::from_legacy_bytes(&headerless).unwrap();
+    assert_eq!(program, Program::new(code.to_vec()));
+    assert_eq!(svm::program::word_bits(&headerless), 32);
+    let e = Program::<i64>::from_legacy_bytes(&headerless).unwrap_err();
+    assert_eq!(e.to_string(), "program has 32-bit words, expected 64-bit");
+
+    assert!(VM::from_bytes(&headerless).is_err());
+    let mut vm = VM::with_config(VMConfig::new().legacy_format(true));
+    vm.load_bytes(&headerless).unwrap();
+    assert_eq!(vm.program(), code);
+}
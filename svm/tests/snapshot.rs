@@ -0,0 +1,205 @@
+//! Saves, restores and resumes programs, and reads damaged snapshots.
+
+use svm::instructions::*;
+use svm::io::BufferIo;
+use svm::program::Program;
+use svm::snapshot::{self, Snapshot};
+use svm::{ExitReason, VMConfig, VMError, Word, VM};
+
+/// Writes i * i to memory[i] for i from 1 to 99, printing every i from a subroutine that
+/// increments it.
+fn squares<W: Word>() -> Vec<W> {
+    let code = [
+        1, DUP, DUP, MUL, OVR, STOR, 16, CALL, DUP, OUT, DUP, 100, 1, JG, HALT, NOP, INC, RET,
+    ];
+    code.into_iter().map(W::from).collect()
+}
+
+/// The state a run ends in, with the output of every part of it.
+#[derive(Debug, PartialEq)]
+struct Outcome<W> {
+    result: Result<ExitReason, VMError>,
+    output: String,
+    stack: Vec<W>,
+    memory: Vec<W>,
+    call_stack: Vec<usize>,
+    ip: usize,
+}
+
+fn outcome<W: Word>(
+    vm: &VM<BufferIo, W>,
+    result: Result<ExitReason, VMError>,
+    output: String,
+) -> Outcome<W> {
+    Outcome {
+        result,
+        output,
+        stack: vm.stack().to_vec(),
+        memory: vm.memory().to_vec(),
+        call_stack: vm.call_stack().to_vec(),
+        ip: vm.ip(),
+    }
+}
+
+fn vm<W: Word>() -> VM<BufferIo, W> {
+    let mut vm = VM::with_config_and_io(VMConfig::new(), BufferIo::new(""));
+    vm.load_program(Program::new(squares()));
+    vm
+}
+
+/// Runs `steps` instructions, saves a snapshot to bytes and resumes from it in a new VM.
+fn resumed<W: Word>(steps: u64) -> Outcome<W> {
+    let mut first = vm::<W>();
+    assert_eq!(first.run_for(steps).unwrap(), ExitReason::Yielded);
+    let mut bytes = Vec::new();
+    first.snapshot().write(&mut bytes).unwrap();
+
+    assert_eq!(snapshot::word_bits(&bytes[..]).unwrap(), W::BITS);
+    let snapshot = Snapshot::<W>::read(&bytes[..]).unwrap();
+    assert_eq!(snapshot, first.snapshot());
+    let mut second = VM::with_config_and_io(snapshot.config, BufferIo::new(""));
+    second.restore(snapshot);
+    let result = second.run();
+    let output = first.io().output().to_string() + second.io().output();
+    outcome(&second, result, output)
+}
+
+fn check_resume<W: Word>() {
+    let mut uninterrupted = vm::<W>();
+    let result = uninterrupted.run();
+    let expected = outcome(
+        &uninterrupted,
+        result,
+        uninterrupted.io().output().to_string(),
+    );
+    assert_eq!(expected.result, Ok(ExitReason::Halted));
+    // 8 and 9 stop inside the subroutine
+    for steps in [1, 2, 7, 8, 9, 100, 1001] {
+        assert_eq!(resumed::<W>(steps), expected, "after {steps} steps");
+    }
+}
+
+#[test]
+fn resume_32() {
+    check_resume::<i32>();
+}
+
+#[test]
+fn resume_64() {
+    check_resume::<i64>();
+}
+
+#[test]
+fn word_size_mismatch() {
+    let mut bytes = Vec::new();
+    vm::<i64>().snapshot().write(&mut bytes).unwrap();
+    let e = Snapshot::<i32>::read(&bytes[..]).unwrap_err();
+    assert_eq!(e.to_string(), "snapshot has 64-bit words, expected 32-bit");
+}
+
+#[test]
+fn save_and_load() {
+    let mut vm = vm::<i32>();
+    vm.run_for(50).unwrap();
+    let path = std::env::temp_dir().join(format!("svm-snapshot-{}", std::process::id()));
+    let path = path.to_str().unwrap();
+    vm.snapshot().save(path).unwrap();
+    assert_eq!(Snapshot::load(path).unwrap(), vm.snapshot());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn damaged() {
+    let mut bytes = Vec::new();
+    vm::<i32>().snapshot().write(&mut bytes).unwrap();
+    for len in 0..bytes.len() {
+        assert!(Snapshot::<i32>::read(&bytes[..len]).is_err(), "{len} bytes");
+    }
+    let error = |bytes: &[u8]| Snapshot::<i32>::read(bytes).unwrap_err().to_string();
+    let mut foreign = bytes.clone();
+    foreign[0] = b'X';
+    assert_eq!(error(&foreign), "not a snapshot file");
+    let mut newer = bytes.clone();
+    newer[4] = 9;
+    assert_eq!(error(&newer), "unsupported snapshot version 9");
+    let mut flag = bytes;
+    // the growable stack flag follows the header and two sizes
+    flag[28] = 2;
+    assert_eq!(error(&flag), "invalid flag 2");
+}
+
+#[test]
+fn huge_length() {
+    let mut bytes = Vec::new();
+    vm::<i32>().snapshot().write(&mut bytes).unwrap();
+    // the program length follows the 12 byte header and the configuration
+    let at = 12 + 8 + 8 + 1 + 8 + 8 + 1;
+    bytes[at..at + 8].copy_from_slice(&(u64::MAX / 2).to_le_bytes());
+    let e = Snapshot::<i32>::read(&bytes[..]).unwrap_err();
+    assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn exit_status() {
+    let mut exited = VM::with_program(vec![3, EXIT]);
+    assert_eq!(exited.run().unwrap(), ExitReason::Exited(3));
+    let mut bytes = Vec::new();
+    exited.snapshot().write(&mut bytes).unwrap();
+    let snapshot = Snapshot::<i32>::read(&bytes[..]).unwrap();
+    assert_eq!(snapshot.exit_status, Some(3));
+    let mut restored = VM::with_config(snapshot.config);
+    restored.restore(snapshot);
+    assert_eq!(restored.run().unwrap(), ExitReason::Exited(3));
+
+    // version 1 has no word size and ends before the exit status
+    let mut bytes = Vec::new();
+    vm::<i32>().snapshot().write(&mut bytes).unwrap();
+    bytes[4] = 1;
+    bytes.drain(8..12);
+    bytes.pop();
+    let snapshot = Snapshot::<i32>::read(&bytes[..]).unwrap();
+    assert_eq!(snapshot.exit_status, None);
+    assert_eq!(snapshot.program, squares::<i32>());
+}
+
+/// Runs `svm` with `args`, returning its exit code and output.
+fn svm(args: &[&str]) -> (i32, String) {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_svm"))
+        .args(args)
+        .stderr(std::process::Stdio::null())
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    (output.status.code().unwrap(), stdout)
+}
+
+#[test]
+fn resume_from_the_command_line() {
+    let dir = std::env::temp_dir();
+    let id = std::process::id();
+    let program = dir.join(format!("svm-squares-{id}"));
+    let snapshot = dir.join(format!("svm-squares-{id}.snapshot"));
+    let (program, snapshot) = (program.to_str().unwrap(), snapshot.to_str().unwrap());
+    let file = std::fs::File::create(program).unwrap();
+    Program::new(squares::<i32>()).write(file).unwrap();
+
+    let mut all = vm::<i32>();
+    all.run().unwrap();
+    let (code, first) = svm(&["--max-steps", "300", "--snapshot", snapshot, program]);
+    assert_eq!(code, 65);
+    // the snapshot brings its own layout, which these options would silently not change
+    for option in [
+        &["--stack-size", "8"][..],
+        &["--growable-stack"],
+        &["--legacy"],
+    ] {
+        let args = [&["resume"], option, &[snapshot]].concat();
+        assert_eq!(svm(&args), (64, String::new()), "{option:?}");
+    }
+    let (code, second) = svm(&["resume", snapshot]);
+    assert_eq!(code, 0);
+    assert_eq!(first + &second, all.io().output());
+
+    std::fs::remove_file(program).unwrap();
+    std::fs::remove_file(snapshot).unwrap();
+}
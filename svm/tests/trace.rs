@@ -0,0 +1,122 @@
+//! Traces programs in both formats.
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use svm::instructions::*;
+use svm::source_map::{SourceLocation, SourceMap};
+use svm::trace::{TraceFormat, Tracer};
+use svm::VM;
+
+/// Collects what a tracer writes.
+#[derive(Clone, Default)]
+struct Shared(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Runs `program` with a tracer and returns the trace.
+fn trace(program: Vec<i32>, format: TraceFormat) -> String {
+    let out = Shared::default();
+    let mut vm = VM::with_program(program);
+    vm.set_tracer(Some(Tracer::new(out.clone(), format)));
+    let _ = vm.run();
+    let bytes = out.0.borrow().clone();
+    String::from_utf8(bytes).unwrap()
+}
+
+#[test]
+fn text() {
+    let program = vec![5, 2, ADD, 0, STOR, 1, 2, 3, 4, 5, POP, ADD, ADD, ADD, ADD];
+    let trace = trace(program, TraceFormat::Text);
+    let lines: Vec<_> = trace.lines().collect();
+    assert_eq!(lines.len(), 15);
+    assert_eq!(lines[0], "     0  LIT            5  [] -> [5]");
+    assert_eq!(lines[2], "     2  ADD               [5 2] -> [7]");
+    assert_eq!(lines[4], "     4  STOR           0  [7 0] -> []");
+    // only the top of the stack is shown
+    assert_eq!(
+        lines[10],
+        "    10  POP               [2 3 4 5] -> [1 2 3 4]"
+    );
+    assert_eq!(
+        lines[14],
+        "    14  ADD               [10] -> error StackUnderflow"
+    );
+}
+
+#[test]
+fn json() {
+    let trace = trace(vec![7, DUP, 6, JMP, 1, 0, LOAD], TraceFormat::Json);
+    let lines: Vec<_> = trace.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            r#"{"ip":0,"op":"LIT","operand":7,"before":[],"after":[7]}"#,
+            r#"{"ip":1,"op":"DUP","operand":null,"before":[7],"after":[7,7]}"#,
+            r#"{"ip":2,"op":"LIT","operand":6,"before":[7,7],"after":[7,7,6]}"#,
+            r#"{"ip":3,"op":"JMP","operand":6,"before":[7,7,6],"after":[7,7]}"#,
+            r#"{"ip":6,"op":"LOAD","operand":7,"before":[7,7],"after":[7,0]}"#,
+        ]
+    );
+}
+
+#[test]
+fn push() {
+    let trace = trace(vec![PUSH, -3, PUSH, 4, ADD], TraceFormat::Text);
+    let lines: Vec<_> = trace.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "     0  PUSH          -3  [] -> [-3]",
+            "     2  PUSH           4  [-3] -> [-3 4]",
+            "     4  ADD               [-3 4] -> [1]",
+        ]
+    );
+}
+
+#[test]
+fn source_positions() {
+    let mut map = SourceMap::new("dir/a \"b\".asm");
+    map.insert(0, SourceLocation { line: 3, column: 5 });
+    for format in [TraceFormat::Text, TraceFormat::Json] {
+        let out = Shared::default();
+        let mut vm = VM::with_program(vec![1, POP]);
+        let tracer = Tracer::new(out.clone(), format).with_source_map(map.clone());
+        vm.set_tracer(Some(tracer));
+        vm.run().unwrap();
+        let trace = String::from_utf8(out.0.borrow().clone()).unwrap();
+        let lines: Vec<_> = trace.lines().collect();
+        let expected = match format {
+            TraceFormat::Text => [
+                r#"     0  LIT            1  [] -> [1]  ; dir/a "b".asm:3:5"#,
+                "     1  POP               [1] -> []",
+            ],
+            TraceFormat::Json => [
+                r#"{"ip":0,"op":"LIT","operand":1,"before":[],"file":"dir/a \"b\".asm","line":3,"column":5,"after":[1]}"#,
+                r#"{"ip":1,"op":"POP","operand":null,"before":[1],"after":[]}"#,
+            ],
+        };
+        assert_eq!(lines, expected);
+    }
+}
+
+#[test]
+fn stop_tracing() {
+    let out = Shared::default();
+    let mut vm = VM::with_program(vec![1, 2, 3]);
+    vm.set_tracer(Some(Tracer::new(out.clone(), TraceFormat::Text)));
+    vm.step().unwrap();
+    assert!(vm.take_tracer().is_some());
+    vm.run().unwrap();
+    assert_eq!(out.0.borrow().iter().filter(|&&b| b == b'\n').count(), 1);
+}
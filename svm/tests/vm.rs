@@ -0,0 +1,518 @@
+//! Runs small programs through the public API of the VM.
+
+use svm::instructions::*;
+use svm::io::BufferIo;
+use svm::program::Program;
+use svm::{ErrorKind, ExitReason, VMConfig, VMError, VM};
+
+#[test]
+fn runs() {
+    let mut vm = VM::with_program(vec![1, 2, ADD, HALT]);
+    assert_eq!(vm.run().unwrap(), ExitReason::Halted);
+    assert_eq!(vm.stack(), [3]);
+    assert_eq!((vm.sp(), vm.ip()), (1, 4));
+    assert!(vm.hf());
+}
+
+#[test]
+fn steps() {
+    let mut vm = VM::with_program(vec![1, 2, ADD, HALT]);
+    for _ in 0..3 {
+        assert_eq!(vm.step().unwrap(), None);
+    }
+    assert_eq!(vm.stack(), [3]);
+    assert_eq!(vm.step().unwrap(), Some(ExitReason::Halted));
+    // a stopped program stays where it is
+    assert_eq!(vm.step().unwrap(), Some(ExitReason::Halted));
+    assert_eq!(vm.ip(), 4);
+}
+
+#[test]
+fn end_of_program() {
+    let mut vm = VM::with_program(vec![5, 6]);
+    assert_eq!(vm.run().unwrap(), ExitReason::EndOfProgram);
+    assert_eq!(vm.stack(), [5, 6]);
+    assert!(!vm.hf());
+    assert_eq!(VM::new().run().unwrap(), ExitReason::EndOfProgram);
+}
+
+#[test]
+fn memory() {
+    let mut vm = VM::with_program(vec![7, 3, STOR, 3, LOAD, 1, LOAD, HALT]);
+    vm.run().unwrap();
+    assert_eq!(vm.memory()[..4], [0, 0, 0, 7]);
+    assert_eq!(vm.stack(), [7, 0]);
+}
+
+#[test]
+fn flags() {
+    let mut vm = VM::with_program(vec![RF]);
+    assert!(!vm.rf());
+    vm.run().unwrap();
+    assert!(vm.rf());
+}
+
+#[test]
+fn from_bytes() {
+    let code = vec![1, 2, ADD, HALT];
+    let bytes = Program::new(code.clone()).to_bytes().unwrap();
+    let vm = VM::from_bytes(&bytes).unwrap();
+    assert_eq!(vm.program(), code);
+    assert!(VM::from_bytes(&bytes[1..]).is_err());
+}
+
+#[test]
+fn exit() {
+    let mut vm = VM::with_program(vec![1, 42, EXIT, 2]);
+    assert_eq!(vm.run().unwrap(), ExitReason::Exited(42));
+    assert_eq!(vm.stack(), [1]);
+    assert!(vm.hf());
+    assert_eq!(vm.step().unwrap(), Some(ExitReason::Exited(42)));
+
+    let mut vm = VM::with_program(vec![EXIT]);
+    assert_eq!(vm.run().unwrap_err().kind, ErrorKind::StackUnderflow);
+    assert_eq!(ErrorKind::StackUnderflow.exit_code(), 70);
+}
+
+#[test]
+fn push() {
+    let mut vm = VM::with_program(vec![PUSH, -5, PUSH, 7, ADD, PUSH, PUSH]);
+    assert_eq!(vm.step().unwrap(), None);
+    assert_eq!((vm.ip(), vm.stack()), (2, &[-5][..]));
+    assert_eq!(vm.run().unwrap(), ExitReason::EndOfProgram);
+    assert_eq!(vm.stack(), [2, PUSH]);
+
+    // a PUSH counts as one instruction and without an operand is unknown
+    let mut vm = VM::with_config(VMConfig::new().max_steps(Some(2)));
+    vm.load_program(Program::new(vec![PUSH, -1, PUSH, -2, PUSH, -3]));
+    assert_eq!(vm.run().unwrap(), ExitReason::Yielded);
+    assert_eq!(vm.stack(), [-1, -2]);
+    let mut vm = VM::with_program(vec![1, PUSH]);
+    assert_eq!(
+        vm.run().unwrap_err().kind,
+        ErrorKind::UnknownInstruction(PUSH.into())
+    );
+
+    // jumping to the operand runs it as an instruction
+    let mut vm = VM::with_program(vec![3, JMP, PUSH, DUP]);
+    assert_eq!(vm.run().unwrap_err().kind, ErrorKind::StackUnderflow);
+}
+
+#[test]
+fn errors() {
+    for (program, error) in [
+        (vec![1, ADD], ErrorKind::StackUnderflow),
+        (vec![1, 5000, STOR], ErrorKind::InvalidMemoryAddress),
+        (vec![-1000], ErrorKind::UnknownInstruction(-1000)),
+    ] {
+        let mut vm = VM::with_program(program.clone());
+        assert_eq!(vm.run().unwrap_err().kind, error, "{program:?}");
+    }
+}
+
+#[test]
+fn error_state() {
+    let mut vm = VM::with_program(vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 0, DIV]);
+    let e = vm.run().unwrap_err();
+    assert_eq!(e.kind, ErrorKind::DivisionByZero);
+    assert_eq!((e.ip, e.opcode), (10, Some(DIV as i64)));
+    assert_eq!(e.stack_depth, 10);
+    assert_eq!(e.stack_top, [3, 4, 5, 6, 7, 8, 9, 0]);
+    assert_eq!(
+        e.to_string(),
+        "division by zero at 10 (DIV), stack depth 10 [... 3 4 5 6 7 8 9 0]"
+    );
+
+    let e = VM::with_program(vec![ADD]).run().unwrap_err();
+    assert_eq!(e.to_string(), "stack underflow at 0 (ADD), stack depth 0");
+}
+
+/// The stack after running `program`.
+fn stack_after(program: Vec<i32>) -> Vec<i32> {
+    let mut vm = VM::with_program(program);
+    vm.run().unwrap();
+    vm.stack().to_vec()
+}
+
+#[test]
+fn arithmetic_wraps() {
+    const MAX: i32 = i32::MAX;
+    // i32::MIN has no literal
+    let min = [MAX, NEG, DEC];
+    assert_eq!(stack_after(vec![MAX, 1, ADD]), [i32::MIN]);
+    assert_eq!(stack_after([&min[..], &[1, SUB]].concat()), [MAX]);
+    assert_eq!(stack_after(vec![65536, 65536, MUL]), [0]);
+    assert_eq!(stack_after([&min[..], &[NEG]].concat()), [i32::MIN]);
+    assert_eq!(stack_after(vec![MAX, INC, DEC]), [MAX]);
+    assert_eq!(stack_after([&min[..], &[1, NEG, MOD]].concat()), [0]);
+    assert_eq!(stack_after(vec![7, 3, MOD, 7, 3, DIV]), [1, 2]);
+    // shift amounts are taken modulo 32
+    assert_eq!(stack_after(vec![1, 33, SHL, MAX, 31, SHR]), [2, 0]);
+}
+
+/// The stack after running `program` with 64-bit words.
+fn wide_stack_after(program: &[i64]) -> Vec<i64> {
+    let mut vm: VM<BufferIo, i64> = VM::with_config_and_io(VMConfig::new(), BufferIo::new(""));
+    vm.load_program(Program::new(program.to_vec()));
+    vm.run().unwrap();
+    vm.stack().to_vec()
+}
+
+#[test]
+fn wide_words() {
+    const MAX: i64 = i64::MAX;
+    let (add, mul, shl, neg) = (ADD.into(), MUL.into(), SHL.into(), NEG.into());
+    assert_eq!(wide_stack_after(&[65536, 65536, mul]), [1 << 32]);
+    assert_eq!(wide_stack_after(&[MAX, 1, add]), [i64::MIN]);
+    // shift amounts are taken modulo 64
+    assert_eq!(wide_stack_after(&[1, 33, shl, 1, 65, shl]), [1 << 33, 2]);
+    assert_eq!(wide_stack_after(&[1 << 40, neg]), [-(1 << 40)]);
+
+    let mut vm: VM<BufferIo, i64> =
+        VM::with_config_and_io(VMConfig::new(), BufferIo::new("-9000000000\n"));
+    vm.load_program(Program::new(vec![IN.into(), DUP.into(), add, OUT.into()]));
+    vm.run().unwrap();
+    assert_eq!(vm.io().output(), "-18000000000\n");
+}
+
+#[test]
+fn division_traps() {
+    for (program, kind, ip) in [
+        (vec![1, 0, DIV], ErrorKind::DivisionByZero, 2),
+        (vec![5, 1, 0, MOD], ErrorKind::DivisionByZero, 3),
+        (
+            vec![i32::MAX, NEG, DEC, 1, NEG, DIV],
+            ErrorKind::ArithmeticOverflow,
+            5,
+        ),
+    ] {
+        let e = VM::with_program(program.clone()).run().unwrap_err();
+        assert_eq!((e.kind, e.ip), (kind, ip), "{program:?}");
+    }
+}
+
+#[test]
+fn jumps() {
+    // counts down from 3 in a loop starting right after the first instruction
+    let mut vm = VM::with_program(vec![3, DEC, DUP, 0, 1, JL]);
+    assert_eq!(vm.run().unwrap(), ExitReason::EndOfProgram);
+    assert_eq!(vm.stack(), [0]);
+
+    let mut vm = VM::with_program(vec![1, 0, JMP]);
+    for _ in 0..3 {
+        vm.step().unwrap();
+    }
+    assert_eq!((vm.ip(), vm.stack()), (0, &[1][..]));
+
+    // far past the end of data memory
+    let mut program = vec![NOP; 3000];
+    program[..2].copy_from_slice(&[2999, JMP]);
+    program[2999] = HALT;
+    let mut vm = VM::with_program(program);
+    assert_eq!(vm.run().unwrap(), ExitReason::Halted);
+    assert_eq!(vm.ip(), 3000);
+}
+
+#[test]
+fn invalid_jump_targets() {
+    for program in [vec![100, JMP], vec![1, 1, 4, JE], vec![0, NEG, DEC, JMP]] {
+        let target = if program.len() == 2 {
+            100
+        } else {
+            program.len() as i32
+        };
+        let target = if program[1] == NEG { -1 } else { target };
+        let mut vm = VM::with_program(program.clone());
+        assert_eq!(
+            vm.run().unwrap_err().kind,
+            ErrorKind::InvalidJumpTarget(target.into()),
+            "{program:?}"
+        );
+    }
+}
+
+/// Pushes 1 `n` times.
+fn pushes(n: usize) -> Vec<i32> {
+    let mut program = vec![1; n];
+    program.push(HALT);
+    program
+}
+
+#[test]
+fn sizes() {
+    let mut vm = VM::with_config(VMConfig::new().stack_size(4).memory_size(2));
+    assert_eq!(vm.memory().len(), 2);
+    vm.load_program(Program::new(vec![1, 1, STOR, 1, 2, STOR]));
+    assert_eq!(vm.run().unwrap_err().kind, ErrorKind::InvalidMemoryAddress);
+    assert_eq!(vm.memory(), [0, 1]);
+
+    let mut vm = VM::with_config(VMConfig::new().stack_size(4));
+    vm.load_program(Program::new(pushes(5)));
+    assert_eq!(vm.run().unwrap_err().kind, ErrorKind::StackOverflow);
+    assert_eq!(vm.sp(), 4);
+}
+
+#[test]
+fn growable_stack() {
+    let config = VMConfig::new()
+        .stack_size(2)
+        .growable_stack(true)
+        .max_stack_size(100);
+    let mut vm = VM::with_config(config);
+    vm.load_program(Program::new(pushes(100)));
+    assert_eq!(vm.run().unwrap(), ExitReason::Halted);
+    assert_eq!(vm.sp(), 100);
+
+    let mut vm = VM::with_config(config);
+    vm.load_program(Program::new(pushes(101)));
+    assert_eq!(vm.run().unwrap_err().kind, ErrorKind::StackOverflow);
+    assert_eq!(vm.sp(), 100);
+}
+
+#[test]
+fn declared_sizes() {
+    let program = Program {
+        stack_size: Some(8),
+        memory_size: Some(4000),
+        ..Program::new(vec![1, 3999, STOR])
+    };
+    let mut vm = VM::with_config(VMConfig::new().stack_size(2).memory_size(10));
+    vm.load_program(program.clone());
+    vm.load_program(program.clone());
+    assert_eq!(vm.memory().len(), 4000);
+    assert_eq!(vm.run().unwrap(), ExitReason::EndOfProgram);
+    assert_eq!(vm.memory()[3999], 1);
+    assert_eq!(vm.sp(), 0);
+
+    // declared sizes never shrink the configured ones
+    let mut vm = VM::with_config(VMConfig::new().memory_size(5000));
+    vm.load_program(program);
+    assert_eq!(vm.memory().len(), 5000);
+}
+
+#[test]
+fn initialized_data() {
+    let program = Program {
+        data: vec![7, 8, 9],
+        ..Program::new(vec![2, LOAD, 0, LOAD, ADD])
+    };
+    let mut vm = VM::with_config(VMConfig::new().memory_size(2));
+    vm.load_program(program);
+    assert_eq!(vm.memory(), [7, 8, 9]);
+    vm.run().unwrap();
+    assert_eq!(vm.stack(), [16]);
+
+    // data goes to the start of memory and leaves the rest alone
+    let mut vm = VM::with_config(VMConfig::new().memory_size(4));
+    vm.load_program(Program::new(vec![5, 3, STOR]));
+    vm.run().unwrap();
+    vm.load_program(Program {
+        data: vec![1],
+        ..Program::default()
+    });
+    assert_eq!(vm.memory(), [1, 0, 0, 5]);
+}
+
+#[test]
+fn subroutines() {
+    // calls a doubling routine at 7 twice, from the routine at 11 that calls it
+    let program = vec![
+        5, 11, CALL, HALT, NOP, NOP, NOP, DUP, ADD, RET, NOP, 7, CALL, 7, CALL, RET,
+    ];
+    let mut vm = VM::with_program(program);
+    for _ in 0..5 {
+        vm.step().unwrap();
+    }
+    assert_eq!((vm.ip(), vm.call_stack()), (7, &[3, 13][..]));
+    assert_eq!(vm.run().unwrap(), ExitReason::Halted);
+    assert_eq!(vm.stack(), [20]);
+    assert!(vm.call_stack().is_empty());
+}
+
+#[test]
+fn call_stack_errors() {
+    let mut vm = VM::with_config(VMConfig::new().call_stack_size(3));
+    vm.load_program(Program::new(vec![0, CALL]));
+    assert_eq!(vm.run().unwrap_err().kind, ErrorKind::CallStackOverflow);
+    assert_eq!(vm.call_stack(), [2, 2, 2]);
+
+    let mut vm = VM::with_program(vec![RET]);
+    assert_eq!(vm.run().unwrap_err().kind, ErrorKind::CallStackUnderflow);
+    let mut vm = VM::with_program(vec![9, CALL]);
+    assert_eq!(vm.run().unwrap_err().kind, ErrorKind::InvalidJumpTarget(9));
+    assert!(vm.call_stack().is_empty());
+}
+
+/// Counts up in memory[0] forever, with a fused op at every even address.
+const COUNTER: [i32; 8] = [0, LOAD, INC, DUP, 0, STOR, 0, JMP];
+
+/// A VM that has executed `steps` instructions of `COUNTER` one at a time.
+fn stepped(steps: u64) -> VM {
+    let mut vm = VM::with_program(COUNTER.to_vec());
+    for _ in 0..steps {
+        assert_eq!(vm.step().unwrap(), None);
+    }
+    vm
+}
+
+fn assert_same_state(vm: &VM, expected: &VM, context: &str) {
+    assert_eq!(vm.ip(), expected.ip(), "{context}");
+    assert_eq!(vm.stack(), expected.stack(), "{context}");
+    assert_eq!(vm.memory()[0], expected.memory()[0], "{context}");
+}
+
+#[test]
+fn fuel_runs_out_like_steps() {
+    // odd budgets end between a literal and the instruction it is fused with
+    for fuel in 0..40 {
+        let mut vm = VM::with_config(VMConfig::new().max_steps(Some(fuel)));
+        vm.load_program(Program::new(COUNTER.to_vec()));
+        assert_eq!(vm.run().unwrap(), ExitReason::Yielded);
+        assert_eq!(vm.fuel(), Some(0));
+        assert_same_state(&vm, &stepped(fuel), &format!("fuel {fuel}"));
+        // stepping does not go past the budget either
+        assert_eq!(vm.step().unwrap(), Some(ExitReason::Yielded));
+
+        vm.add_fuel(3);
+        assert_eq!(vm.run().unwrap(), ExitReason::Yielded);
+        assert_same_state(&vm, &stepped(fuel + 3), &format!("fuel {fuel} + 3"));
+    }
+}
+
+#[test]
+fn run_for_resumes_like_steps() {
+    for chunk in 1..10 {
+        let mut vm = VM::with_config(VMConfig::new().max_steps(Some(100)));
+        vm.load_program(Program::new(COUNTER.to_vec()));
+        let mut executed = 0;
+        while executed < 100 {
+            assert_eq!(vm.run_for(chunk).unwrap(), ExitReason::Yielded);
+            executed = (executed + chunk).min(100);
+            assert_eq!(vm.fuel(), Some(100 - executed));
+            let context = format!("{executed} in chunks of {chunk}");
+            assert_same_state(&vm, &stepped(executed), &context);
+        }
+    }
+}
+
+#[test]
+fn unlimited_fuel() {
+    let mut vm = VM::with_program(COUNTER.to_vec());
+    assert_eq!(vm.fuel(), None);
+    assert_eq!(vm.run_for(1000).unwrap(), ExitReason::Yielded);
+    assert_same_state(&vm, &stepped(1000), "1000 steps");
+    vm.add_fuel(5);
+    assert_eq!(vm.fuel(), None);
+
+    vm.set_fuel(Some(2));
+    assert_eq!(vm.run().unwrap(), ExitReason::Yielded);
+    assert_same_state(&vm, &stepped(1002), "1002 steps");
+    // a program that stops is not yielded, even with fuel left
+    let mut vm = VM::with_config(VMConfig::new().max_steps(Some(10)));
+    vm.load_program(Program::new(vec![1, HALT]));
+    assert_eq!(vm.run_for(5).unwrap(), ExitReason::Halted);
+    assert_eq!(vm.fuel(), Some(8));
+}
+
+/// Runs `program` one instruction at a time and then all at once, checking that both stop in
+/// the same state, and returns the result.
+fn run_both(program: &[i32], config: VMConfig) -> Result<ExitReason, VMError> {
+    let vm = || {
+        let mut vm = VM::with_config(config);
+        vm.load_program(Program::new(program.to_vec()));
+        vm
+    };
+    let mut stepped = vm();
+    let stepped_result = loop {
+        match stepped.step() {
+            Ok(Some(reason)) => break Ok(reason),
+            Ok(None) => {}
+            Err(e) => break Err(e),
+        }
+    };
+    let mut run = vm();
+    let run_result = run.run();
+    assert_eq!(run_result, stepped_result);
+    assert_eq!(run.ip(), stepped.ip());
+    assert_eq!(run.stack(), stepped.stack());
+    assert_eq!(run.memory(), stepped.memory());
+    assert_eq!(run.call_stack(), stepped.call_stack());
+    assert_eq!(run.fuel(), stepped.fuel());
+    run_result
+}
+
+#[test]
+fn fused_ops_run_like_steps() {
+    let config = VMConfig::new().memory_size(16).max_steps(Some(1000));
+    // a literal address followed by each instruction that takes one
+    let programs = [
+        vec![4, JMP, 1, OUT, 9, 0, STOR, 0, LOAD, HALT],
+        vec![
+            1, 1, 6, JE, 2, HALT, 3, 2, 11, JNE, 0, 5, 4, 16, JG, HALT, 6, 7, 21, JGE, HALT, 9,
+        ],
+        vec![1, 2, 6, JL, 5, HALT, 2, 1, 12, JLE, HALT, HALT, 7, HALT],
+        vec![5, CALL, 1, 2, HALT, 3, RET],
+        vec![3, 15, STOR, 15, LOAD, 15, LOAD, ADD],
+        // a literal past the end of the program is not fused
+        vec![100, JMP],
+        // a literal before an instruction that takes none is not fused
+        vec![6, DUP, ADD, 3, 4, 9, JMP],
+    ];
+    for program in programs {
+        let _ = run_both(&program, config);
+    }
+    assert_eq!(
+        run_both(&[3, 15, STOR, 15, LOAD, 15, LOAD, ADD], config),
+        Ok(ExitReason::EndOfProgram)
+    );
+}
+
+#[test]
+fn fused_load_fails_at_load() {
+    let config = VMConfig::new().memory_size(16).max_steps(Some(100));
+    let e = run_both(&[7, 100, LOAD, HALT], config).unwrap_err();
+    assert_eq!(e.kind, ErrorKind::InvalidMemoryAddress);
+    assert_eq!(e.ip, 2);
+    assert_eq!(e.opcode, Some(LOAD as i64));
+    assert_eq!(e.stack_top, [7]);
+}
+
+#[test]
+fn fused_ops_fail_like_their_instructions() {
+    let config = (VMConfig::new().memory_size(16))
+        .call_stack_size(4)
+        .max_steps(Some(100));
+    for (program, kind, ip) in [
+        (vec![1, 100, STOR], ErrorKind::InvalidMemoryAddress, 2),
+        (vec![3, JE, HALT, HALT], ErrorKind::StackUnderflow, 1),
+        (vec![1, 2, JL, HALT], ErrorKind::StackUnderflow, 2),
+        (vec![0, CALL], ErrorKind::CallStackOverflow, 1),
+    ] {
+        let e = run_both(&program, config).unwrap_err();
+        assert_eq!((e.kind, e.ip), (kind, ip), "{program:?}");
+        assert_eq!(e.opcode, Some(program[ip] as i64));
+    }
+    // a full stack fails at the literal, which does not fit
+    let config = config.stack_size(1);
+    let e = run_both(&[1, 2, LOAD], config).unwrap_err();
+    assert_eq!((e.kind, e.ip), (ErrorKind::StackOverflow, 1));
+}
+
+#[test]
+fn exit_codes() {
+    for (reason, code) in [
+        (ExitReason::Halted, Some(0)),
+        (ExitReason::EndOfProgram, Some(0)),
+        (ExitReason::Exited(0), Some(0)),
+        (ExitReason::Exited(2), Some(2)),
+        (ExitReason::Exited(63), Some(63)),
+        (ExitReason::Exited(64), Some(63)),
+        (ExitReason::Exited(255), Some(63)),
+        (ExitReason::Exited(256), Some(63)),
+        (ExitReason::Exited(-1), Some(63)),
+        (ExitReason::Exited(i64::MIN), Some(63)),
+        (ExitReason::Yielded, None),
+    ] {
+        assert_eq!(reason.exit_code(), code, "{reason:?}");
+    }
+}